@@ -6,12 +6,19 @@ pub trait ArrayMatrix {
     fn column(&self) -> usize;
     /// Get row and column length as a tuple.
     fn size(&self) -> (usize, usize);
+    /// The matrix type returned by `transpose`, with rows and columns swapped.
+    type Transpose;
     /// Get transpose of the matrix.
-    fn transpose(&self) -> Self;
-    /// Transpose in place.
+    fn transpose(&self) -> Self::Transpose;
+    /// Transpose in place. Only square matrices can be transposed in place.
     fn transpose_mut(&mut self);
     /// Swaps two elements in a matrix.
     fn swap(&mut self, a: (usize, usize), b: (usize, usize));
+    /// The type of the matrix elements.
+    type Element;
+    /// Sum of the diagonal elements, or zero for an empty matrix. For a non-square matrix the
+    /// diagonal runs to the smaller of the row and column lengths.
+    fn trace(&self) -> Self::Element;
 }
 
 // Non-macro test implementation
@@ -36,6 +43,9 @@ mod tests {
     }
 
     impl ArrayMatrix for NonMacroMatrix {
+        type Transpose = NonMacroMatrix;
+        type Element = f32;
+
         fn row(&self) -> usize {
             3
         }
@@ -53,6 +63,10 @@ mod tests {
             self.0.swap(a_i * cols + a_j, b_i * cols + b_j);
         }
 
+        fn trace(&self) -> f32 {
+            (0..self.row().min(self.column())).map(|i| self[(i, i)]).sum()
+        }
+
         fn transpose(&self) -> Self {
             let mut trans = NonMacroMatrix([0f32; 9]);
             for i in 0..self.0.len() {
@@ -60,7 +74,7 @@ mod tests {
                 let c = i % self.column();
                 // println!("({0}, {1}): {2} <-> ({1}, {0}): {3}",
                 //    r, c, self[(r, c)], self[(c, r)]);
-                trans[(c, r)] = self[(r, c)].clone();
+                trans[(c, r)] = self[(r, c)];
             }
             trans
         }
@@ -69,22 +83,18 @@ mod tests {
             let rows = self.row();
             let cols = self.column();
             let mut positions = (0..self.0.len()).map(|i| (i / cols, i % cols));
-            loop {
-                if let Some((r, c)) = positions.next() {
-                    if r == c {
-                        if r < rows - 1 {
-                            // Consume the rest of the row to avoid double swapping
-                            let _ = positions.nth(rows - r - 2);
-                        }
-                    } else {
-                        let a = r * cols + c;
-                        let b = c * rows + r;
-                        // assert_eq!(self[(r, c)], self.0[a]);
-                        // assert_eq!(self[(c, r)], self.0[b]);
-                        self.0.swap(a, b);
+            while let Some((r, c)) = positions.next() {
+                if r == c {
+                    if r < rows - 1 {
+                        // Consume the rest of the row to avoid double swapping
+                        let _ = positions.nth(rows - r - 2);
                     }
                 } else {
-                    break;
+                    let a = r * cols + c;
+                    let b = c * rows + r;
+                    // assert_eq!(self[(r, c)], self.0[a]);
+                    // assert_eq!(self[(c, r)], self.0[b]);
+                    self.0.swap(a, b);
                 }
             }
         }
@@ -126,8 +136,8 @@ mod tests {
 
         fn add(self, other: NonMacroMatrix) -> NonMacroMatrix {
             let mut a = [0f32; 9];
-            for i in 0..a.len() {
-                a[i] = self.0[i].clone() + other.0[i].clone();
+            for (i, x) in a.iter_mut().enumerate() {
+                *x = self.0[i] + other.0[i];
             }
             NonMacroMatrix(a)
         }
@@ -138,8 +148,8 @@ mod tests {
 
         fn add(self, other: f32) -> NonMacroMatrix {
             let mut a = [0f32; 9];
-            for i in 0..a.len() {
-                a[i] = self.0[i].clone() + other;
+            for (i, x) in a.iter_mut().enumerate() {
+                *x = self.0[i] + other;
             }
             NonMacroMatrix(a)
         }
@@ -148,7 +158,7 @@ mod tests {
     impl AddAssign for NonMacroMatrix {
         fn add_assign(&mut self, other: NonMacroMatrix) {
             for i in 0..self.0.len() {
-                self.0[i] += other.0[i].clone();
+                self.0[i] += other.0[i];
             }
         }
     }
@@ -156,7 +166,7 @@ mod tests {
     impl AddAssign<f32> for NonMacroMatrix {
         fn add_assign(&mut self, other: f32) {
             for i in 0..self.0.len() {
-                self.0[i] -= other;
+                self.0[i] += other;
             }
         }
     }
@@ -166,8 +176,8 @@ mod tests {
 
         fn sub(self, other: NonMacroMatrix) -> NonMacroMatrix {
             let mut a = [0f32; 9];
-            for i in 0..a.len() {
-                a[i] = self.0[i].clone() - other.0[i].clone();
+            for (i, x) in a.iter_mut().enumerate() {
+                *x = self.0[i] - other.0[i];
             }
             NonMacroMatrix(a)
         }
@@ -178,8 +188,8 @@ mod tests {
 
         fn sub(self, other: f32) -> NonMacroMatrix {
             let mut a = [0f32; 9];
-            for i in 0..a.len() {
-                a[i] = self.0[i].clone() - other;
+            for (i, x) in a.iter_mut().enumerate() {
+                *x = self.0[i] - other;
             }
             NonMacroMatrix(a)
         }
@@ -188,7 +198,7 @@ mod tests {
     impl SubAssign for NonMacroMatrix {
         fn sub_assign(&mut self, other: NonMacroMatrix) {
             for i in 0..self.0.len() {
-                self.0[i] -= other.0[i].clone();
+                self.0[i] -= other.0[i];
             }
         }
     }
@@ -206,8 +216,8 @@ mod tests {
 
         fn mul(self, other: f32) -> NonMacroMatrix {
             let mut a = [0f32; 9];
-            for i in 0..a.len() {
-                a[i] = self.0[i].clone() * other;
+            for (i, x) in a.iter_mut().enumerate() {
+                *x = self.0[i] * other;
             }
             NonMacroMatrix(a)
         }
@@ -252,20 +262,16 @@ mod tests {
         fn mul(self, other: T) -> NonMacroMatrix {
             assert_eq!(self.row(), other.column());
             let mut result = NonMacroMatrix([0.; 9]);
-            let mut positions = (0..result.0.len()).map(|i| {
+            let positions = (0..result.0.len()).map(|i| {
                 (i / self.column(), i % self.column())
             });
 
-            loop {
-                if let Some((i, j)) = positions.next() {
-                    let mut sum = 0 as f32;
-                    for k in 0..other.row() {
-                        sum += self[(i, k)].clone() * other[(k, j)].clone();
-                    }
-                    result[(i, j)] = sum;
-                } else {
-                    break
+            for (i, j) in positions {
+                let mut sum = 0 as f32;
+                for k in 0..other.row() {
+                    sum += self[(i, k)] * other[(k, j)];
                 }
+                result[(i, j)] = sum;
             }
             result
         }
@@ -281,6 +287,13 @@ mod tests {
         assert_eq!(m.column(), 3);
     }
 
+    #[test]
+    fn trace() {
+        let m = NonMacroMatrix([1., 2., 3., 4., 5., 6., 7., 8., 9.]);
+
+        assert_eq!(m.trace(), 15.);
+    }
+
     #[test]
     fn multiply() {
         let m_a = NonMacroMatrix([1., 2., 3., 4., 5., 6., 7., 8., 9.]);
@@ -0,0 +1,95 @@
+/// Element types with an additive identity.
+pub trait Zero {
+    /// Returns the additive identity, `0`.
+    fn zero() -> Self;
+}
+
+/// Element types with a multiplicative identity.
+pub trait One {
+    /// Returns the multiplicative identity, `1`.
+    fn one() -> Self;
+}
+
+/// Element types that can be approximated by an `f64`, used by the floating point methods.
+pub trait ToF64 {
+    /// Converts the value to an `f64`, with the semantics of an `as` cast for primitives.
+    fn to_f64(self) -> f64;
+}
+
+/// Element types that can be built from an `f64`, used by the floating point methods.
+pub trait FromF64 {
+    /// Converts an `f64` to the element type, with the semantics of an `as` cast for
+    /// primitives.
+    fn from_f64(value: f64) -> Self;
+}
+
+macro_rules! impl_element {
+    ($($t:ty)*) => {
+        $(
+            impl Zero for $t {
+                fn zero() -> Self {
+                    0 as $t
+                }
+            }
+
+            impl One for $t {
+                fn one() -> Self {
+                    1 as $t
+                }
+            }
+
+            impl ToF64 for $t {
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+            }
+
+            impl FromF64 for $t {
+                fn from_f64(value: f64) -> Self {
+                    value as $t
+                }
+            }
+        )*
+    };
+}
+
+impl_element!(i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize f32 f64);
+
+/// Element types with a fixed-size little-endian encoding, used by `to_bytes` and `from_bytes`.
+pub trait ByteElement: Sized {
+    /// Tag identifying the element type in a binary header.
+    const TAG: u8;
+    /// Number of bytes in the encoding of one element.
+    const SIZE: usize;
+    /// Appends the little-endian encoding of the value to `out`.
+    fn write_le(&self, out: &mut Vec<u8>);
+    /// Decodes a value from exactly `SIZE` little-endian bytes.
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_byte_element {
+    ($($t:ty => $tag:expr),*) => {
+        $(
+            impl ByteElement for $t {
+                const TAG: u8 = $tag;
+                const SIZE: usize = ::std::mem::size_of::<$t>();
+
+                fn write_le(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn read_le(bytes: &[u8]) -> Self {
+                    let mut buf = [0u8; ::std::mem::size_of::<$t>()];
+                    buf.copy_from_slice(bytes);
+                    <$t>::from_le_bytes(buf)
+                }
+            }
+        )*
+    };
+}
+
+impl_byte_element!(
+    i8 => 1, i16 => 2, i32 => 3, i64 => 4, i128 => 5,
+    u8 => 6, u16 => 7, u32 => 8, u64 => 9, u128 => 10,
+    f32 => 11, f64 => 12
+);
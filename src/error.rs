@@ -0,0 +1,148 @@
+use std::error::Error;
+use std::fmt;
+
+/// Error returned by fallible matrix operations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MatrixError {
+    /// The requested position lies outside the matrix.
+    IndexOutOfBounds {
+        /// Requested row.
+        i: usize,
+        /// Requested column.
+        j: usize,
+    },
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MatrixError::IndexOutOfBounds { i, j } => {
+                write!(f, "index ({}, {}) is out of bounds", i, j)
+            }
+        }
+    }
+}
+
+impl Error for MatrixError {}
+
+/// Error returned when input data does not have the shape of the matrix type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShapeError {
+    /// The number of rows does not match the matrix type.
+    RowCount {
+        /// Rows required by the matrix type.
+        expected: usize,
+        /// Rows provided.
+        found: usize,
+    },
+    /// A row does not have as many elements as the matrix has columns.
+    RowLength {
+        /// Index of the offending row.
+        row: usize,
+        /// Columns required by the matrix type.
+        expected: usize,
+        /// Elements provided in the row.
+        found: usize,
+    },
+    /// A block does not fit inside the matrix at the requested position.
+    BlockOutOfBounds {
+        /// Position of the top left element of the block.
+        at: (usize, usize),
+        /// Row and column length of the block.
+        size: (usize, usize),
+    },
+}
+
+impl fmt::Display for ShapeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ShapeError::RowCount { expected, found } => {
+                write!(f, "expected {} rows, found {}", expected, found)
+            }
+            ShapeError::RowLength { row, expected, found } => {
+                write!(f, "expected {} elements in row {}, found {}", expected, row, found)
+            }
+            ShapeError::BlockOutOfBounds { at, size } => {
+                write!(f, "block of size {:?} at {:?} does not fit in the matrix", size, at)
+            }
+        }
+    }
+}
+
+impl Error for ShapeError {}
+
+/// Error returned when parsing a matrix from text fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The number of rows does not match the matrix type.
+    RowCount {
+        /// Rows required by the matrix type.
+        expected: usize,
+        /// Rows found in the input.
+        found: usize,
+    },
+    /// A row does not have as many elements as the matrix has columns.
+    RowLength {
+        /// Index of the offending row.
+        row: usize,
+        /// Columns required by the matrix type.
+        expected: usize,
+        /// Elements found in the row.
+        found: usize,
+    },
+    /// An element could not be parsed into the element type.
+    InvalidElement {
+        /// Row of the element.
+        row: usize,
+        /// Column of the element.
+        column: usize,
+    },
+    /// The number of columns in a binary header does not match the matrix type.
+    ColumnCount {
+        /// Columns required by the matrix type.
+        expected: usize,
+        /// Columns found in the input.
+        found: usize,
+    },
+    /// The element type tag in a binary header does not match the matrix type.
+    ElementType {
+        /// Tag of the matrix element type.
+        expected: u8,
+        /// Tag found in the input.
+        found: u8,
+    },
+    /// The binary input is not exactly as long as the header and elements require.
+    ByteLength {
+        /// Bytes required by the matrix type.
+        expected: usize,
+        /// Bytes found in the input.
+        found: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::RowCount { expected, found } => {
+                write!(f, "expected {} rows, found {}", expected, found)
+            }
+            ParseError::RowLength { row, expected, found } => {
+                write!(f, "expected {} elements in row {}, found {}", expected, row, found)
+            }
+            ParseError::InvalidElement { row, column } => {
+                write!(f, "invalid element at ({}, {})", row, column)
+            }
+            ParseError::ColumnCount { expected, found } => {
+                write!(f, "expected {} columns, found {}", expected, found)
+            }
+            ParseError::ElementType { expected, found } => {
+                write!(f, "expected element type tag {}, found {}", expected, found)
+            }
+            ParseError::ByteLength { expected, found } => {
+                write!(f, "expected {} bytes, found {}", expected, found)
+            }
+        }
+    }
+}
+
+impl Error for ParseError {}
@@ -15,5 +15,34 @@
                 unused_qualifications, unused_results)]
 
 pub use array_matrix::ArrayMatrix;
+pub use element::{ByteElement, FromF64, One, ToF64, Zero};
+pub use error::{MatrixError, ParseError, ShapeError};
+pub use pivoting::Pivoting;
+pub use round_mode::RoundMode;
+pub use sparse::SparseMatrix;
 mod array_matrix;
+mod element;
+mod error;
+#[doc(hidden)]
+pub mod linalg;
 mod macros;
+mod pivoting;
+mod round_mode;
+mod sparse;
+
+#[cfg(test)]
+thread_local! {
+    /// Number of identity short-circuits taken by generated `Mul` impls on this thread. It is
+    /// thread-local because the test harness runs other multiplying tests in parallel.
+    #[doc(hidden)]
+    pub static IDENTITY_FAST_PATHS: ::std::cell::Cell<usize> = const { ::std::cell::Cell::new(0) };
+}
+
+/// Called by the identity short-circuit in generated `Mul` impls. It only counts in this crate's
+/// own test build, so that tests can tell the short-circuit apart from a full product.
+#[doc(hidden)]
+#[inline]
+pub fn count_identity_fast_path() {
+    #[cfg(test)]
+    IDENTITY_FAST_PATHS.with(|n| n.set(n.get() + 1));
+}
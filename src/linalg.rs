@@ -0,0 +1,265 @@
+//! Floating point routines shared by the methods that `impl_matrix!` generates. They work on
+//! row-major `f64` buffers so that they do not depend on a particular matrix shape.
+//!
+//! The elimination routines, `lu` and `rref`, treat a pivot as zero when it is no larger than
+//! `f64::EPSILON` times the largest element times the larger dimension. This keeps rounding
+//! errors from turning a singular matrix into a badly conditioned regular one.
+
+use Pivoting;
+
+/// The tolerance below which `lu` and `rref` treat a pivot as zero.
+fn zero_tolerance(a: &[f64], rows: usize, cols: usize) -> f64 {
+    let scale = a.iter().fold(0f64, |m, x| m.max(x.abs()));
+    f64::EPSILON * scale * rows.max(cols) as f64
+}
+
+/// Diagonalizes the symmetric `n x n` matrix `a` with the cyclic Jacobi eigenvalue algorithm.
+///
+/// Returns the eigenvalues in descending order together with a row-major `n x n` matrix whose
+/// columns are the matching orthonormal eigenvectors. Each rotation zeroes one off-diagonal
+/// pair, and convergence is quadratic once the off-diagonal elements are small. The sweeps stop
+/// when the off-diagonal sum of squares falls below 1e-24 of the total, or after 100 sweeps.
+/// Non-finite input gives NaN eigenvalues, which are sorted by `f64::total_cmp` rather than
+/// rejected.
+pub fn symmetric_eigen(mut a: Vec<f64>, n: usize) -> (Vec<f64>, Vec<f64>) {
+    let mut v = vec![0f64; n * n];
+    for i in 0..n {
+        v[i * n + i] = 1.;
+    }
+
+    let total: f64 = a.iter().map(|x| x * x).sum();
+    for _ in 0..100 {
+        let mut off = 0.;
+        for i in 0..n {
+            for j in 0..n {
+                if i != j {
+                    off += a[i * n + j] * a[i * n + j];
+                }
+            }
+        }
+        if off <= 1e-24 * total {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                if a[p * n + q] == 0. {
+                    continue;
+                }
+                let theta = (a[q * n + q] - a[p * n + p]) / (2. * a[p * n + q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+                let c = 1. / (t * t + 1.).sqrt();
+                let s = t * c;
+                // a = J^T * a * J and v = v * J, for the rotation J in the (p, q) plane
+                for k in 0..n {
+                    let (akp, akq) = (a[k * n + p], a[k * n + q]);
+                    a[k * n + p] = c * akp - s * akq;
+                    a[k * n + q] = s * akp + c * akq;
+                    let (vkp, vkq) = (v[k * n + p], v[k * n + q]);
+                    v[k * n + p] = c * vkp - s * vkq;
+                    v[k * n + q] = s * vkp + c * vkq;
+                }
+                for k in 0..n {
+                    let (apk, aqk) = (a[p * n + k], a[q * n + k]);
+                    a[p * n + k] = c * apk - s * aqk;
+                    a[q * n + k] = s * apk + c * aqk;
+                }
+            }
+        }
+    }
+
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&x, &y| a[y * n + y].total_cmp(&a[x * n + x]));
+    let values = order.iter().map(|&k| a[k * n + k]).collect();
+    let mut vectors = vec![0f64; n * n];
+    for i in 0..n {
+        for (j, &k) in order.iter().enumerate() {
+            vectors[i * n + j] = v[i * n + k];
+        }
+    }
+    (values, vectors)
+}
+
+/// Reduces the row-major `rows x cols` matrix `a` to reduced row echelon form in place, using
+/// Gauss-Jordan elimination with partial pivoting, and returns the pivot columns in order.
+///
+/// Elements within the module's zero tolerance are treated as zero, so that rounding errors do
+/// not create spurious pivots.
+pub fn rref(a: &mut [f64], rows: usize, cols: usize) -> Vec<usize> {
+    let tolerance = zero_tolerance(a, rows, cols);
+    let mut pivots = Vec::new();
+    let mut r = 0;
+    for c in 0..cols {
+        if r == rows {
+            break;
+        }
+        let mut pivot = r;
+        for i in r + 1..rows {
+            if a[i * cols + c].abs() > a[pivot * cols + c].abs() {
+                pivot = i;
+            }
+        }
+        if a[pivot * cols + c].abs() <= tolerance {
+            for i in r..rows {
+                a[i * cols + c] = 0.;
+            }
+            continue;
+        }
+        for j in 0..cols {
+            a.swap(r * cols + j, pivot * cols + j);
+        }
+        let p = a[r * cols + c];
+        for j in 0..cols {
+            a[r * cols + j] /= p;
+        }
+        for i in 0..rows {
+            let factor = a[i * cols + c];
+            if i != r && factor != 0. {
+                for j in 0..cols {
+                    a[i * cols + j] -= factor * a[r * cols + j];
+                }
+            }
+        }
+        pivots.push(c);
+        r += 1;
+    }
+    pivots
+}
+
+/// An LU factorization `P * A * Q = L * U` of a square matrix, as computed by `lu`.
+pub struct Lu {
+    n: usize,
+    // L strictly below the diagonal, with an implied unit diagonal, and U on and above it.
+    lu: Vec<f64>,
+    // rows[k] is the row of A moved to row k, and cols[k] the column moved to column k.
+    rows: Vec<usize>,
+    cols: Vec<usize>,
+    swaps: usize,
+}
+
+impl Lu {
+    /// The number of row and column swaps made while pivoting.
+    pub fn swaps(&self) -> usize {
+        self.swaps
+    }
+
+    /// The determinant of A: the product of the pivots, negated for an odd number of swaps.
+    pub fn determinant(&self) -> f64 {
+        let n = self.n;
+        let pivots: f64 = (0..n).map(|k| self.lu[k * n + k]).product();
+        if self.swaps % 2 == 1 { -pivots } else { pivots }
+    }
+
+    /// Solves `A * x = b` by forward and back substitution.
+    pub fn solve(&self, b: &[f64]) -> Vec<f64> {
+        let n = self.n;
+        assert_eq!(b.len(), n);
+        let mut y: Vec<f64> = self.rows.iter().map(|&r| b[r]).collect();
+        for i in 0..n {
+            let lower: f64 = (0..i).map(|k| self.lu[i * n + k] * y[k]).sum();
+            y[i] -= lower;
+        }
+        let mut x = vec![0f64; n];
+        for i in (0..n).rev() {
+            let upper: f64 = (i + 1..n).map(|k| self.lu[i * n + k] * y[k]).sum();
+            y[i] = (y[i] - upper) / self.lu[i * n + i];
+            x[self.cols[i]] = y[i];
+        }
+        x
+    }
+}
+
+/// Factors the row-major `n x n` matrix `a` by Gaussian elimination, choosing pivots as
+/// `pivoting` specifies.
+///
+/// Returns `None` when a chosen pivot is within the module's zero tolerance. With partial or
+/// full pivoting that means the matrix is singular; without pivoting it may only mean that a
+/// diagonal element is too small to divide by.
+pub fn lu(mut a: Vec<f64>, n: usize, pivoting: Pivoting) -> Option<Lu> {
+    assert_eq!(a.len(), n * n);
+    let tolerance = zero_tolerance(&a, n, n);
+    let mut rows: Vec<usize> = (0..n).collect();
+    let mut cols: Vec<usize> = (0..n).collect();
+    let mut swaps = 0;
+    for c in 0..n {
+        let (mut pr, mut pc) = (c, c);
+        match pivoting {
+            Pivoting::None => {}
+            Pivoting::Partial => {
+                for r in c + 1..n {
+                    if a[r * n + c].abs() > a[pr * n + c].abs() {
+                        pr = r;
+                    }
+                }
+            }
+            Pivoting::Full => {
+                for r in c..n {
+                    for k in c..n {
+                        if a[r * n + k].abs() > a[pr * n + pc].abs() {
+                            pr = r;
+                            pc = k;
+                        }
+                    }
+                }
+            }
+        }
+        if a[pr * n + pc].abs() <= tolerance {
+            return None;
+        }
+        if pr != c {
+            for j in 0..n {
+                a.swap(c * n + j, pr * n + j);
+            }
+            rows.swap(c, pr);
+            swaps += 1;
+        }
+        if pc != c {
+            for i in 0..n {
+                a.swap(i * n + c, i * n + pc);
+            }
+            cols.swap(c, pc);
+            swaps += 1;
+        }
+        let p = a[c * n + c];
+        for r in c + 1..n {
+            let factor = a[r * n + c] / p;
+            a[r * n + c] = factor;
+            for j in c + 1..n {
+                a[r * n + j] -= factor * a[c * n + j];
+            }
+        }
+    }
+    Some(Lu { n, lu: a, rows, cols, swaps })
+}
+
+/// Estimates the relative rounding error of an element type from `round_trip`, which converts an
+/// `f64` to the element type and back. This is about half the machine epsilon for floats, and 1
+/// for integers, which truncate 1/3 to 0.
+pub fn roundoff<F: Fn(f64) -> f64>(round_trip: F) -> f64 {
+    let third = 1. / 3.;
+    (round_trip(third) - third).abs() / third
+}
+
+/// Panics unless `x` solves `a * x = b` for the row-major `n x n` matrices `a`, `x` and `b` as
+/// well as a backward stable solve followed by rounding `x` to an element type with relative
+/// rounding error `roundoff` allows.
+///
+/// The bound is `8 n u (|a| |x| + |b|)` in the infinity norm, with `u` being `roundoff` but at
+/// least `f64::EPSILON`. `|x|` grows with the condition number of `a`, so ill-conditioned inputs
+/// pass; what fails is a solution that is wrong for its inputs. Element types with a `roundoff`
+/// of one half or more, such as integers, are not checked.
+pub fn check_solution(what: &str, a: &[f64], x: &[f64], b: &[f64], n: usize, roundoff: f64) {
+    if roundoff >= 0.5 {
+        return;
+    }
+    let norm = |m: &[f64]| m.chunks(n).map(|r| r.iter().map(|v| v.abs()).sum::<f64>()).fold(0f64, f64::max);
+    let mut residual = 0f64;
+    for i in 0..n {
+        let row: f64 = (0..n)
+            .map(|j| ((0..n).map(|k| a[i * n + k] * x[k * n + j]).sum::<f64>() - b[i * n + j]).abs())
+            .sum();
+        residual = residual.max(row);
+    }
+    let bound = 8. * n as f64 * roundoff.max(f64::EPSILON) * (norm(a) * norm(x) + norm(b));
+    assert!(residual <= bound, "{} lost accuracy: residual {} exceeds the rounding bound {}",
+            what, residual, bound);
+}
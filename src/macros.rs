@@ -2,6 +2,12 @@
 /// tuple of (row, column) size arguments and implements the
 /// [`ArrayMatrix`](trait.ArrayMatrix.html) trait.
 ///
+/// The element type must be `Copy + PartialEq + Debug`, implement [`Zero`](trait.Zero.html)
+/// and [`One`](trait.One.html), and support the arithmetic and compound assignment operators.
+/// Methods that work in floating point are only available when it also implements
+/// [`ToF64`](trait.ToF64.html) and [`FromF64`](trait.FromF64.html), as all primitive numeric
+/// types do.
+///
 /// Example:
 ///
 /// ```
@@ -36,495 +42,4182 @@
 /// # }
 /// ```
 ///
+/// The struct and its inherent methods are private to the invoking module unless the name is
+/// prefixed with a visibility, as in `impl_matrix!(pub MyMatrix([f32; (3, 3)]))` or
+/// `impl_matrix!(pub(crate) MyMatrix([f32; (3, 3)]))`. The array field always stays private, so
+/// other modules construct matrices with `from_array`.
+///
+/// A non-square matrix type needs a companion type with the rows and columns swapped, so that
+/// `transpose` has somewhere to put its result. Name it with `transpose:` on both types:
+///
+/// ```
+/// # #[macro_use] extern crate array_matrix;
+/// # fn main() {
+/// # use array_matrix::ArrayMatrix;
+/// # use std::ops::{Index, IndexMut, Add, AddAssign, Sub, SubAssign, Mul, MulAssign, Div, DivAssign};
+/// # use std::fmt;
+/// impl_matrix!(Matrix2x3([i32; (2, 3)]), transpose: Matrix3x2);
+/// impl_matrix!(Matrix3x2([i32; (3, 2)]), transpose: Matrix2x3);
+///
+/// let m = Matrix2x3([1, 2, 3, 4, 5, 6]);
+/// assert_eq!(m.transpose(), Matrix3x2([1, 4, 2, 5, 3, 6]));
+/// # }
+/// ```
+///
 #[macro_export]
 macro_rules! impl_matrix {
-    ($st:ident([$t:ty; ($row:expr, $col:expr)])) => {
-        struct $st([$t; $row * $col]);
+    ($vis:vis $st:ident([$t:ty; ($row:expr, $col:expr)])) => {
+        impl_matrix!($vis $st([$t; ($row, $col)]), transpose: $st);
+    };
+    ($vis:vis $st:ident([$t:ty; ($row:expr, $col:expr)]), transpose: $tr:ident) => {
+        // Methods and impls that need more of the element type than `Zero`, `One` and the
+        // arithmetic operators say so with a higher-ranked bound such as
+        // `where for<'a> $t: $crate::ToF64`. The bound does not mention `'a`, but being
+        // higher-ranked makes the compiler check it where the method is used instead of where
+        // the macro expands, so matrices of custom element types still compile without those
+        // traits. The bodies behind such a bound must not build `[x; $row * $col]` arrays, which
+        // fail to evaluate when the bound does not hold; they start from `$st(self.0)`,
+        // `zeros()` or `from_fn` instead.
+        #[derive(Clone, Copy)]
+        $vis struct $st([$t; $row * $col]);
 
+        // Matrices are `Copy`, but large ones are still cheaper to borrow in `to_*` conversions.
+        #[allow(clippy::wrong_self_convention)]
         impl $st {
             // Create matrix from an array of type [$t; $row * $col]
             #[allow(dead_code)]
-            fn from_array(array: [$t; $row * $col]) -> Self {
+            $vis fn from_array(array: [$t; $row * $col]) -> Self {
                 $st(array)
             }
 
-            // Creates an identity matrix.
+            // Create matrix by calling `f` for each position in row-major order.
             #[allow(dead_code)]
-            fn identity() -> $st {
-                let mut m = $st([0 as $t; $row * $col]);
+            $vis fn from_fn<F: FnMut((usize, usize)) -> $t>(mut f: F) -> Self {
+                let mut m = $st([<$t as $crate::Zero>::zero(); $row * $col]);
                 for i in 0..$row {
-                    m[(i, i)] = 1 as $t;
+                    for j in 0..$col {
+                        m[(i, j)] = f((i, j));
+                    }
                 }
                 m
             }
-        }
-
-        impl ArrayMatrix for $st {
-            fn row(&self) -> usize {
-                $row
-            }
-
-            fn column(&self) -> usize {
-                $col
-            }
 
-            fn size(&self) -> (usize, usize) {
-                (self.row(), self.column())
+            // Create matrix by calling `f` for each position in row-major order, stopping at
+            // the first error.
+            #[allow(dead_code)]
+            $vis fn try_from_fn<E, F: FnMut((usize, usize)) -> Result<$t, E>>(mut f: F) -> Result<Self, E> {
+                let mut m = $st([<$t as $crate::Zero>::zero(); $row * $col]);
+                for i in 0..$row {
+                    for j in 0..$col {
+                        m[(i, j)] = f((i, j))?;
+                    }
+                }
+                Ok(m)
             }
 
-            fn swap(&mut self, (a_i, a_j): (usize, usize), (b_i, b_j): (usize, usize)) {
-                self.0.swap(a_i * $col + a_j, b_i * $col + b_j);
+            // Create matrix from nested row vectors, checking that there are `$row` rows of
+            // `$col` elements each.
+            #[allow(dead_code)]
+            $vis fn from_rows_vec(rows: Vec<Vec<$t>>) -> Result<Self, $crate::ShapeError> {
+                if rows.len() != $row {
+                    return Err($crate::ShapeError::RowCount { expected: $row, found: rows.len() });
+                }
+                let mut m = $st([<$t as $crate::Zero>::zero(); $row * $col]);
+                for (i, row) in rows.into_iter().enumerate() {
+                    if row.len() != $col {
+                        return Err($crate::ShapeError::RowLength {
+                            row: i,
+                            expected: $col,
+                            found: row.len(),
+                        });
+                    }
+                    for (j, x) in row.into_iter().enumerate() {
+                        m[(i, j)] = x;
+                    }
+                }
+                Ok(m)
             }
 
-            fn transpose(&self) -> Self {
-                let mut trans = $st([0 as $t; $row * $col]);
-                for i in 0..self.0.len() {
-                   let r = i / $col;
-                   let c = i % $col;
-                   trans[(c, r)] = self[(r, c)].clone();
+            // Splits the elements, in row-major order, into rows of `row_len` elements, or returns
+            // `None` if `row_len` does not divide the element count. The shape of a generated type
+            // is fixed, so the rows are returned nested, ready for `from_rows_vec` of a type with
+            // that shape.
+            #[allow(dead_code)]
+            $vis fn with_row_len(&self, row_len: usize) -> Option<Vec<Vec<$t>>> {
+                if row_len == 0 || !self.0.len().is_multiple_of(row_len) {
+                    return None;
                 }
-                trans
+                Some(self.0.chunks(row_len).map(|r| r.to_vec()).collect())
             }
 
-            fn transpose_mut(&mut self) {
-                let mut positions = (0..self.0.len()).map(|i| {
-                   (i / $col, i % $col)
-                });
-                loop {
-                    if let Some((r, c)) = positions.next() {
-                        //println!("({}, {}) {}", r, c, self[(r, c)]);
-                        if r == c {
-                            if r < $row - 1 {
-                                // Consume the rest of the row to avoid double swapping
-                                let _ = positions.nth($row - r - 2);
-                            }
-                        } else {
-                            let a = r * $col + c;
-                            let b = c * $row + r;
-                            //assert_eq!(self[(r, c)], self.0[a]);
-                            //assert_eq!(self[(c, r)], self.0[b]);
-                            self.0.swap(a, b);
+            // Create matrix from the product `a * b`, where `a` has `$row` rows and `b` has
+            // `$col` columns.
+            #[allow(dead_code)]
+            $vis fn from_product<A, B>(a: &A, b: &B) -> Self
+                where A: ArrayMatrix + Index<(usize, usize), Output=$t>,
+                      B: ArrayMatrix + Index<(usize, usize), Output=$t>
+            {
+                assert_eq!(a.column(), b.row());
+                assert_eq!((a.row(), b.column()), ($row, $col));
+                let mut m = $st([<$t as $crate::Zero>::zero(); $row * $col]);
+                for i in 0..$row {
+                    for j in 0..$col {
+                        let mut sum = <$t as $crate::Zero>::zero();
+                        for k in 0..a.column() {
+                            sum += a[(i, k)] * b[(k, j)];
                         }
-                    } else {
-                        break
+                        m[(i, j)] = sum;
                     }
                 }
+                m
             }
-        }
 
-        impl Index<(usize, usize)> for $st {
-            type Output = $t;
-
-            #[inline]
-            fn index(&self, (i, j): (usize, usize)) -> &$t {
-                assert!(i < $row && j < $col);
-                &self.0[i * $col + j]
+            // Create matrix from a sparse matrix of the same size, with zero for every element
+            // that is not stored.
+            #[allow(dead_code)]
+            $vis fn from_sparse(sparse: &$crate::SparseMatrix<$t>) -> Self {
+                assert_eq!(sparse.size(), ($row, $col));
+                let mut m = $st([<$t as $crate::Zero>::zero(); $row * $col]);
+                for &(pos, x) in sparse.entries() {
+                    m[pos] = x;
+                }
+                m
             }
-        }
 
-        impl IndexMut<(usize, usize)> for $st {
+            // Creates a matrix with `value` in every element.
+            #[allow(dead_code)]
+            $vis fn filled(value: $t) -> $st {
+                $st([value; $row * $col])
+            }
 
-            #[inline]
-            fn index_mut(&mut self, (i, j): (usize, usize)) -> &mut $t {
-                assert!(i < $row && j < $col);
-                &mut self.0[i * $col + j]
+            // Creates a matrix of zeros, using the element type's `Zero` impl.
+            #[allow(dead_code)]
+            $vis fn zeros() -> $st {
+                $st::filled(<$t as $crate::Zero>::zero())
             }
-        }
 
-        impl fmt::Debug for $st {
-            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-                f.debug_list().entries(self.0.iter()).finish()
+            // Creates an identity matrix.
+            #[allow(dead_code)]
+            $vis fn identity() -> $st {
+                let mut m = $st([<$t as $crate::Zero>::zero(); $row * $col]);
+                for i in 0..$row {
+                    m[(i, i)] = <$t as $crate::One>::one();
+                }
+                m
             }
-        }
 
-        impl Eq for $st {}
+            // Returns the rows of this matrix with `row` inserted before row `at`, so that later
+            // rows shift down. The result has one row more than this type, so it is returned as
+            // nested rows. Panics if `at` is past the last row or `row` does not have `$col`
+            // elements.
+            #[allow(dead_code)]
+            $vis fn insert_row(&self, at: usize, row: &[$t]) -> Vec<Vec<$t>> {
+                assert!(at <= $row, "cannot insert a row at {} in a matrix with {} rows", at, $row);
+                assert_eq!(row.len(), $col, "inserted row has {} elements, expected {}", row.len(), $col);
+                let mut rows: Vec<Vec<$t>> = self.0.chunks($col).map(|r| r.to_vec()).collect();
+                rows.insert(at, row.to_vec());
+                rows
+            }
 
-        impl PartialEq for $st {
-            fn eq(&self, other: &$st) -> bool {
-                self.0[..] == other.0[..]
+            // Returns the rows of this matrix with `column` inserted before column `at`, so that
+            // later columns shift right. The result has one column more than this type, so it is
+            // returned as nested rows. Panics if `at` is past the last column or `column` does
+            // not have `$row` elements.
+            #[allow(dead_code)]
+            $vis fn insert_column(&self, at: usize, column: &[$t]) -> Vec<Vec<$t>> {
+                assert!(at <= $col, "cannot insert a column at {} in a matrix with {} columns", at, $col);
+                assert_eq!(column.len(), $row, "inserted column has {} elements, expected {}", column.len(), $row);
+                let mut rows: Vec<Vec<$t>> = self.0.chunks($col).map(|r| r.to_vec()).collect();
+                for (r, x) in rows.iter_mut().zip(column.iter()) {
+                    r.insert(at, *x);
+                }
+                rows
             }
-        }
 
-        impl Add for $st {
-            type Output = $st;
+            // Returns row `at` and the rows that remain without it, or `None` if `at` is out of
+            // bounds. The remaining matrix has one row fewer than this type, so it is returned as
+            // nested rows.
+            #[allow(dead_code)]
+            $vis fn remove_row(&self, at: usize) -> Option<(Vec<$t>, Vec<Vec<$t>>)> {
+                if at >= $row {
+                    return None;
+                }
+                let mut rows: Vec<Vec<$t>> = self.0.chunks($col).map(|r| r.to_vec()).collect();
+                let removed = rows.remove(at);
+                Some((removed, rows))
+            }
 
-            fn add(self, other: $st) -> $st {
-                let mut a = [0 as $t; $row * $col];
-                for i in 0..a.len() {
-                    a[i] = self.0[i].clone() + other.0[i].clone();
+            // Returns column `at` and the rows that remain without it, or `None` if `at` is out
+            // of bounds. Every remaining row has `$col - 1` elements.
+            #[allow(dead_code)]
+            $vis fn remove_column(&self, at: usize) -> Option<(Vec<$t>, Vec<Vec<$t>>)> {
+                if at >= $col {
+                    return None;
                 }
-                $st(a)
+                let mut rows: Vec<Vec<$t>> = self.0.chunks($col).map(|r| r.to_vec()).collect();
+                let removed = rows.iter_mut().map(|r| r.remove(at)).collect();
+                Some((removed, rows))
             }
-        }
 
-        impl Add<$t> for $st {
-            type Output = $st;
+            // Creates a square matrix with `diag` on the main diagonal, `lower` just below it
+            // and `upper` just above it.
+            #[allow(dead_code)]
+            $vis fn tridiagonal(lower: $t, diag: $t, upper: $t) -> $st {
+                assert!($row == $col, "tridiagonal requires a square matrix");
+                let mut m = $st([<$t as $crate::Zero>::zero(); $row * $col]);
+                for i in 0..$row {
+                    m[(i, i)] = diag;
+                    if i > 0 {
+                        m[(i, i - 1)] = lower;
+                    }
+                    if i + 1 < $col {
+                        m[(i, i + 1)] = upper;
+                    }
+                }
+                m
+            }
 
-            fn add(self, other: $t) -> $st {
-                let mut a = [0 as $t; $row * $col];
-                for i in 0..a.len() {
-                    a[i] = self.0[i].clone() + other;
+            // Gets a reference to the element at `(i, j)`, or an error carrying the
+            // coordinates if they are out of range.
+            #[allow(dead_code)]
+            $vis fn at(&self, i: usize, j: usize) -> Result<&$t, $crate::MatrixError> {
+                if i < $row && j < $col {
+                    Ok(&self.0[i * $col + j])
+                } else {
+                    Err($crate::MatrixError::IndexOutOfBounds { i, j })
                 }
-                $st(a)
             }
-        }
 
-        impl AddAssign for $st {
-            fn add_assign(&mut self, other: $st) {
-                for i in 0..self.0.len() {
-                    self.0[i] += other.0[i];
+            // Gets a reference to the element at `(i, j)`, or `None` if it is out of range.
+            #[allow(dead_code)]
+            $vis fn get(&self, (i, j): (usize, usize)) -> Option<&$t> {
+                if i < $row && j < $col {
+                    Some(&self.0[i * $col + j])
+                } else {
+                    None
                 }
             }
-        }
 
-        impl AddAssign<$t> for $st {
-            fn add_assign(&mut self, other: $t) {
-                for i in 0..self.0.len() {
-                    self.0[i] += other;
+            // Gets a mutable reference to the element at `(i, j)`, or `None` if it is out of
+            // range.
+            #[allow(dead_code)]
+            $vis fn get_mut(&mut self, (i, j): (usize, usize)) -> Option<&mut $t> {
+                if i < $row && j < $col {
+                    Some(&mut self.0[i * $col + j])
+                } else {
+                    None
                 }
             }
-        }
 
-        impl Sub for $st {
-            type Output = $st;
+            // Borrows the backing array as a flat row-major slice of `$row * $col` elements.
+            #[allow(dead_code)]
+            $vis fn as_slice(&self) -> &[$t] {
+                &self.0[..]
+            }
 
-            fn sub(self, other: $st) -> $st {
-                let mut a = [0 as $t; $row * $col];
-                for i in 0..a.len() {
-                    a[i] = self.0[i].clone() - other.0[i].clone();
-                }
-                $st(a)
+            // Mutably borrows the backing array as a flat row-major slice.
+            #[allow(dead_code)]
+            $vis fn as_mut_slice(&mut self) -> &mut [$t] {
+                &mut self.0[..]
             }
-        }
 
-        impl Sub<$t> for $st {
-            type Output = $st;
+            // Consumes the matrix and returns its row-major backing array.
+            #[allow(dead_code)]
+            $vis fn into_array(self) -> [$t; $row * $col] {
+                self.0
+            }
 
-            fn sub(self, other: $t) -> $st {
-                let mut a = [0 as $t; $row * $col];
-                for i in 0..a.len() {
-                    a[i] = self.0[i].clone() - other;
+            // Creates the companion matrix of the monic polynomial
+            // `x^n + coeffs[n-1] * x^(n-1) + ... + coeffs[1] * x + coeffs[0]`, where `n` is the
+            // matrix size. Coefficients are given lowest degree first and the leading 1 is
+            // omitted. The eigenvalues of the result are the roots of the polynomial.
+            #[allow(dead_code)]
+            $vis fn companion(coeffs: &[$t]) -> $st {
+                assert!($row == $col, "companion requires a square matrix");
+                assert_eq!(coeffs.len(), $row);
+                let mut m = $st([<$t as $crate::Zero>::zero(); $row * $col]);
+                for i in 0..$row {
+                    if i > 0 {
+                        m[(i, i - 1)] = <$t as $crate::One>::one();
+                    }
+                    m[(i, $col - 1)] = <$t as $crate::Zero>::zero() - coeffs[i];
                 }
-                $st(a)
+                m
             }
-        }
 
-        impl SubAssign for $st {
-            fn sub_assign(&mut self, other: $st) {
-                for i in 0..self.0.len() {
-                    self.0[i] -= other.0[i];
+            // Checks whether the matrix is a square identity matrix.
+            #[allow(dead_code)]
+            $vis fn is_identity(&self) -> bool {
+                if $row != $col {
+                    return false;
+                }
+                for i in 0..$row {
+                    for j in 0..$col {
+                        let expected = if i == j { <$t as $crate::One>::one() } else { <$t as $crate::Zero>::zero() };
+                        if self[(i, j)] != expected {
+                            return false;
+                        }
+                    }
                 }
+                true
             }
-        }
 
-        impl SubAssign<$t> for $st {
-            fn sub_assign(&mut self, other: $t) {
-                for i in 0..self.0.len() {
-                    self.0[i] -= other;
+            // Converts to a coordinate-list sparse matrix that stores only the nonzero elements.
+            #[allow(dead_code)]
+            $vis fn to_sparse(&self) -> $crate::SparseMatrix<$t> {
+                let mut sparse = $crate::SparseMatrix::new($row, $col);
+                for i in 0..$row {
+                    for j in 0..$col {
+                        if self[(i, j)] != <$t as $crate::Zero>::zero() {
+                            let _ = sparse.insert((i, j), self[(i, j)]);
+                        }
+                    }
                 }
+                sparse
             }
-        }
 
-        impl<T> Mul<T> for $st
-            where T: ArrayMatrix + Index<(usize, usize), Output=$t>
-        {
-            type Output = $st;
+            // Checks whether every element is within `eps` of zero.
+            #[allow(dead_code)]
+            $vis fn is_zero(&self, eps: f64) -> bool
+                where for<'a> $t: $crate::ToF64
+            {
+                self.0.iter().all(|x| $crate::ToF64::to_f64(*x).abs() <= eps)
+            }
 
-            fn mul(self, other: T) -> $st {
-                assert_eq!(self.row(), other.column());
-                let mut result = $st([0 as $t; $row * $col]);
-                let mut positions = (0..result.0.len()).map(|i| {
-                    (i / self.column(), i % self.column())
-                });
+            // Checks whether every element is exactly zero, for integer element types.
+            #[allow(dead_code)]
+            $vis fn is_zero_exact(&self) -> bool {
+                self.0.iter().all(|x| *x == <$t as $crate::Zero>::zero())
+            }
 
-                loop {
-                    if let Some((i, j)) = positions.next() {
-                        let mut sum = self[(i, 0)].clone() * other[(0, j)].clone();
-                        for k in 1..other.row() {
-                            sum += self[(i, k)].clone() * other[(k, j)].clone();
-                        }
-                        result[(i, j)] = sum;
-                    } else {
-                        break
+            // Averages a slice of matrices element-wise, returning `None` for an empty slice.
+            // Integer element types use integer division.
+            #[allow(dead_code)]
+            $vis fn average(matrices: &[$st]) -> Option<$st> {
+                if matrices.is_empty() {
+                    return None;
+                }
+                let total: $st = matrices.iter().sum();
+                let one = <$t as $crate::One>::one();
+                let count = matrices.iter().fold(<$t as $crate::Zero>::zero(), |n, _| n + one);
+                Some(total / count)
+            }
+
+            // Computes the linear combination `w_1 * A_1 + w_2 * A_2 + ...` in a single pass.
+            #[allow(dead_code)]
+            $vis fn weighted_sum(pairs: &[($t, &$st)]) -> $st {
+                let mut result = $st([<$t as $crate::Zero>::zero(); $row * $col]);
+                for &(weight, m) in pairs {
+                    for i in 0..result.0.len() {
+                        result.0[i] += weight * m.0[i];
                     }
                 }
                 result
             }
-        }
 
-        impl Mul<$t> for $st {
-            type Output = $st;
-
-            fn mul(self, other: $t) -> $st {
-                let mut a = [0 as $t; $row * $col];
-                for i in 0..a.len() {
-                    a[i] = self.0[i].clone() * other;
+            // Linearly interpolates element-wise as `(1 - t) * self + t * other`.
+            // `t` is not clamped, so values outside [0, 1] extrapolate past either end.
+            #[allow(dead_code)]
+            $vis fn lerp(&self, other: &$st, t: f64) -> $st
+                where for<'a> $t: $crate::ToF64 + $crate::FromF64
+            {
+                let mut result = $st(self.0);
+                for i in 0..result.0.len() {
+                    result.0[i] = <$t as $crate::FromF64>::from_f64((1. - t) * $crate::ToF64::to_f64(self.0[i]) + t * $crate::ToF64::to_f64(other.0[i]));
                 }
-                $st(a)
+                result
             }
-        }
 
-        impl MulAssign<$t> for $st {
-            fn mul_assign(&mut self, other: $t) {
-                for i in 0..self.0.len() {
-                    self.0[i] *= other;
+            // Checks that all elements are nonnegative and every row sums to one within `eps`,
+            // as required of a Markov transition matrix.
+            #[allow(dead_code)]
+            $vis fn is_row_stochastic(&self, eps: f64) -> bool
+                where for<'a> $t: $crate::ToF64
+            {
+                for i in 0..$row {
+                    let mut sum = 0.;
+                    for j in 0..$col {
+                        let x = $crate::ToF64::to_f64(self[(i, j)]);
+                        if x < 0. {
+                            return false;
+                        }
+                        sum += x;
+                    }
+                    if (sum - 1.).abs() > eps {
+                        return false;
+                    }
                 }
+                true
             }
-        }
 
-        impl Div<$t> for $st {
-            type Output = $st;
+            // Copies the `rows x cols` block whose top left element is at `(top, left)`, as
+            // nested row vectors that can be passed to `from_rows_vec` of a matrix type with
+            // that shape.
+            #[allow(dead_code)]
+            $vis fn block(&self, top: usize, left: usize, rows: usize, cols: usize) -> Vec<Vec<$t>> {
+                assert!(top + rows <= $row && left + cols <= $col,
+                        "block of size ({}, {}) at ({}, {}) exceeds matrix size ({}, {})",
+                        rows, cols, top, left, $row, $col);
+                (top..top + rows)
+                    .map(|i| self.0[i * $col + left..i * $col + left + cols].to_vec())
+                    .collect()
+            }
 
-            fn div(self, other: $t) -> $st {
-                let mut a = [0 as $t; $row * $col];
-                for i in 0..a.len() {
-                    a[i] = self.0[i].clone() / other;
+            // Copies `src` into the region whose top left element is at `(top, left)`, leaving
+            // the rest of the matrix untouched. Fails without writing anything if `src` would
+            // extend past the matrix bounds.
+            #[allow(dead_code)]
+            $vis fn set_block<M>(&mut self, top: usize, left: usize, src: &M) -> Result<(), $crate::ShapeError>
+                where M: ArrayMatrix + Index<(usize, usize), Output=$t>
+            {
+                if top + src.row() > $row || left + src.column() > $col {
+                    return Err($crate::ShapeError::BlockOutOfBounds {
+                        at: (top, left),
+                        size: src.size(),
+                    });
                 }
-                $st(a)
+                for i in 0..src.row() {
+                    for j in 0..src.column() {
+                        self[(top + i, left + j)] = src[(i, j)];
+                    }
+                }
+                Ok(())
             }
-        }
 
-        impl DivAssign<$t> for $st {
-            fn div_assign(&mut self, other: $t) {
-                for i in 0..self.0.len() {
-                    self.0[i] /= other;
+            // Swaps rows `a` and `b` and records the swap in the permutation vector `perm`.
+            #[allow(dead_code)]
+            $vis fn swap_rows_tracked(&mut self, a: usize, b: usize, perm: &mut [usize]) {
+                assert!(a < $row && b < $row,
+                        "rows {} and {} out of bounds for a {}x{} matrix", a, b, $row, $col);
+                assert!(perm.len() == $row,
+                        "permutation of length {} does not match a {}x{} matrix", perm.len(), $row, $col);
+                for j in 0..$col {
+                    self.swap((a, j), (b, j));
                 }
+                perm.swap(a, b);
             }
-        }
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use ArrayMatrix;
-    use std::ops::{Index, IndexMut, Add, AddAssign, Sub, SubAssign, Mul, MulAssign, Div, DivAssign};
-    use std::fmt;
 
-    #[test]
-    fn ident() {
-        impl_matrix!(TestMatrix([i32; (2, 2)]));
-        let m = TestMatrix::identity();
+            // Checks that every element is within `epsilon` of the one in `other`, comparing
+            // in the element type. Meant for float element types, where exact equality rarely
+            // survives a product or an inverse. A NaN on either side never compares equal.
+            #[allow(dead_code)]
+            $vis fn approx_eq(&self, other: &$st, epsilon: $t) -> bool
+                where for<'a> $t: PartialOrd
+            {
+                self.0.iter().zip(other.0.iter()).all(|(a, b)| {
+                    let diff = if a > b { *a - *b } else { *b - *a };
+                    diff <= epsilon
+                })
+            }
 
-        assert_eq!(m, TestMatrix::from_array([1, 0, 0, 1]));
-    }
+            // Checks that every element is within the tolerance given for its column,
+            // `eps[j]` for column `j`.
+            #[allow(dead_code)]
+            $vis fn approx_eq_per_column(&self, other: &$st, eps: &[f64]) -> bool
+                where for<'a> $t: $crate::ToF64
+            {
+                assert_eq!(eps.len(), $col);
+                for i in 0..$row {
+                    for j in 0..$col {
+                        if ($crate::ToF64::to_f64(self[(i, j)]) - $crate::ToF64::to_f64(other[(i, j)])).abs() > eps[j] {
+                            return false;
+                        }
+                    }
+                }
+                true
+            }
 
-    #[test]
+            // Lists every position where the two matrices differ, with the element from `self`
+            // followed by the element from `other`.
+            #[allow(dead_code)]
+            $vis fn diff(&self, other: &$st) -> Vec<((usize, usize), $t, $t)> {
+                let mut differences = Vec::new();
+                for i in 0..$row {
+                    for j in 0..$col {
+                        if self[(i, j)] != other[(i, j)] {
+                            differences.push(((i, j), self[(i, j)], other[(i, j)]));
+                        }
+                    }
+                }
+                differences
+            }
+
+            // Creates a new matrix by applying `f` to every element in row-major order.
+            #[allow(dead_code)]
+            $vis fn map<F: FnMut(&$t) -> $t>(&self, mut f: F) -> $st {
+                let mut m = *self;
+                for x in m.0.iter_mut() {
+                    *x = f(x);
+                }
+                m
+            }
+
+            // Creates a new matrix by applying `f` to every position and its element.
+            #[allow(dead_code)]
+            $vis fn map_indexed<F: Fn((usize, usize), $t) -> $t>(&self, f: F) -> $st {
+                let mut m = $st([<$t as $crate::Zero>::zero(); $row * $col]);
+                for i in 0..$row {
+                    for j in 0..$col {
+                        m[(i, j)] = f((i, j), self[(i, j)]);
+                    }
+                }
+                m
+            }
+
+            // Keeps the elements above the diagonal, and the diagonal itself if
+            // `include_diagonal` is set, zeroing the rest.
+            #[allow(dead_code)]
+            $vis fn upper_triangular(&self, include_diagonal: bool) -> $st {
+                self.map_indexed(|(i, j), x| {
+                    if j > i || (include_diagonal && i == j) { x } else { <$t as $crate::Zero>::zero() }
+                })
+            }
+
+            // Keeps the elements below the diagonal, and the diagonal itself if
+            // `include_diagonal` is set, zeroing the rest.
+            #[allow(dead_code)]
+            $vis fn lower_triangular(&self, include_diagonal: bool) -> $st {
+                self.map_indexed(|(i, j), x| {
+                    if i > j || (include_diagonal && i == j) { x } else { <$t as $crate::Zero>::zero() }
+                })
+            }
+
+            // Divides every element by `divisor`, rounding each quotient as `mode` specifies.
+            // Meant for integer element types, where plain `/` always truncates toward zero.
+            #[allow(dead_code)]
+            $vis fn div_round(&self, divisor: $t, mode: $crate::RoundMode) -> $st
+                where for<'a> $t: PartialOrd
+            {
+                let zero = <$t as $crate::Zero>::zero();
+                let one = <$t as $crate::One>::one();
+                self.map_indexed(|_, x| {
+                    let quotient = x / divisor;
+                    let remainder = x - quotient * divisor;
+                    if remainder == zero {
+                        return quotient;
+                    }
+                    // The exact quotient is negative when remainder and divisor differ in sign.
+                    let negative = (remainder < zero) != (divisor < zero);
+                    let away_from_zero = if negative { quotient - one } else { quotient + one };
+                    match mode {
+                        $crate::RoundMode::Trunc => quotient,
+                        $crate::RoundMode::Floor => if negative { away_from_zero } else { quotient },
+                        $crate::RoundMode::Ceil => if negative { quotient } else { away_from_zero },
+                        $crate::RoundMode::Nearest => {
+                            let r = if remainder < zero { zero - remainder } else { remainder };
+                            let d = if divisor < zero { zero - divisor } else { divisor };
+                            if r >= d - r { away_from_zero } else { quotient }
+                        }
+                    }
+                })
+            }
+
+            // Combines all elements with `f`, starting from `identity`. `f` must be associative
+            // and `identity` must leave any element unchanged, because the grouping of the
+            // combinations is not guaranteed and may be split up for parallel evaluation.
+            #[allow(dead_code)]
+            $vis fn reduce<F: Fn($t, $t) -> $t>(&self, identity: $t, f: F) -> $t {
+                self.0.iter().fold(identity, |acc, x| f(acc, *x))
+            }
+
+            // Computes the Frobenius inner product, the sum of the element-wise products.
+            // This equals `trace(self^T * other)` without forming the product.
+            #[allow(dead_code)]
+            $vis fn frobenius_inner(&self, other: &$st) -> $t {
+                let mut sum = <$t as $crate::Zero>::zero();
+                for i in 0..self.0.len() {
+                    sum += self.0[i] * other.0[i];
+                }
+                sum
+            }
+
+            // Adds `alpha * u * v^T` to the matrix in place, without allocating the outer product.
+            #[allow(dead_code)]
+            $vis fn rank1_update(&mut self, alpha: $t, u: &[$t], v: &[$t]) {
+                assert_eq!(u.len(), $row);
+                assert_eq!(v.len(), $col);
+                for i in 0..$row {
+                    let scaled = alpha * u[i];
+                    for j in 0..$col {
+                        self.0[i * $col + j] += scaled * v[j];
+                    }
+                }
+            }
+
+            // Counts the multiply-add operations that `self * other` performs.
+            #[allow(dead_code)]
+            $vis fn flop_count(&self, other: &$st) -> usize {
+                self.row() * other.column() * self.column()
+            }
+
+            // Transforms each column of `x` by the matrix, which is the product `self * x`.
+            // Both operands share a type, so the matrix must be square.
+            #[allow(dead_code)]
+            $vis fn transform_columns(&self, x: &$st) -> $st {
+                assert!($row == $col, "transform_columns requires a square matrix");
+                *self * *x
+            }
+
+            // Computes the similarity transform `p^-1 * self * p`, or `None` if `p` is singular.
+            // This solves `p * x = self * p` with the LU factorization of `p` from
+            // `linalg::lu`, in f64, so accuracy degrades as `p` becomes ill-conditioned. Debug
+            // builds panic if the result, in the element type, fails `p * x = self * p` by more
+            // than rounding allows; see `linalg::check_solution`.
+            #[allow(dead_code)]
+            $vis fn similarity_transform(&self, p: &$st) -> Option<$st>
+                where for<'a> $t: $crate::ToF64 + $crate::FromF64
+            {
+                assert!($row == $col, "similarity_transform requires a square matrix");
+                let n = $row;
+                let to_f64 = |m: &$st| -> Vec<f64> { m.0.iter().map(|x| $crate::ToF64::to_f64(*x)).collect() };
+                let (a, p_f64) = (to_f64(self), to_f64(p));
+                let mut ap = vec![0f64; n * n];
+                for i in 0..n {
+                    for j in 0..n {
+                        ap[i * n + j] = (0..n).map(|k| a[i * n + k] * p_f64[k * n + j]).sum();
+                    }
+                }
+                let lu = $crate::linalg::lu(p_f64, n, $crate::Pivoting::Partial)?;
+
+                let mut result = $st(self.0);
+                for j in 0..n {
+                    let column: Vec<f64> = (0..n).map(|i| ap[i * n + j]).collect();
+                    for (i, x) in lu.solve(&column).into_iter().enumerate() {
+                        result[(i, j)] = <$t as $crate::FromF64>::from_f64(x);
+                    }
+                }
+
+                #[cfg(debug_assertions)]
+                $crate::linalg::check_solution("similarity_transform", &to_f64(p), &to_f64(&result), &ap, n,
+                    $crate::linalg::roundoff(|x| $crate::ToF64::to_f64(<$t as $crate::FromF64>::from_f64(x))));
+                Some(result)
+            }
+
+            // Transposes with a cache-oblivious recursion: the longer side of the block is halved
+            // until the block is at most 16x16, which is then copied with a plain double loop.
+            // The result is the row-major data of the `$col x $row` transpose, so it is correct
+            // for rectangular matrices too.
+            #[allow(dead_code)]
+            $vis fn transpose_recursive(&self) -> [$t; $row * $col] {
+                fn block(src: &[$t], dst: &mut [$t], (r0, r1): (usize, usize), (c0, c1): (usize, usize)) {
+                    if r1 - r0 <= 16 && c1 - c0 <= 16 {
+                        for r in r0..r1 {
+                            for c in c0..c1 {
+                                dst[c * $row + r] = src[r * $col + c];
+                            }
+                        }
+                    } else if r1 - r0 >= c1 - c0 {
+                        let mid = r0 + (r1 - r0) / 2;
+                        block(src, dst, (r0, mid), (c0, c1));
+                        block(src, dst, (mid, r1), (c0, c1));
+                    } else {
+                        let mid = c0 + (c1 - c0) / 2;
+                        block(src, dst, (r0, r1), (c0, mid));
+                        block(src, dst, (r0, r1), (mid, c1));
+                    }
+                }
+
+                let mut trans = [<$t as $crate::Zero>::zero(); $row * $col];
+                block(&self.0, &mut trans, (0, $row), (0, $col));
+                trans
+            }
+
+            // Rounds every element to the nearest integer, with halfway cases rounded away from
+            // zero. The row-major result can be passed to `from_array` of an `i64` matrix type
+            // with the same shape.
+            #[allow(dead_code)]
+            $vis fn round_to_int(&self) -> [i64; $row * $col]
+                where for<'a> $t: $crate::ToF64
+            {
+                self.0.map(|x| $crate::ToF64::to_f64(x).round() as i64)
+            }
+
+            // Approximates the determinant in f64 using LU decomposition with partial pivoting.
+            // Unlike exact integer arithmetic this cannot overflow, which is enough to check the
+            // sign and magnitude of determinants of large integer matrices.
+            #[allow(dead_code)]
+            $vis fn determinant_f64(&self) -> f64
+                where for<'a> $t: $crate::ToF64
+            {
+                self.determinant_verbose().map_or(0., |(det, _)| det)
+            }
+
+            // Like `determinant_f64`, but also returns the number of row swaps made while
+            // pivoting. The determinant is the product of the pivots, negated when the swap count
+            // is odd. Returns `None` when `linalg::lu` finds the matrix singular.
+            #[allow(dead_code)]
+            $vis fn determinant_verbose(&self) -> Option<(f64, usize)>
+                where for<'a> $t: $crate::ToF64
+            {
+                assert!($row == $col, "determinant_verbose requires a square matrix");
+                let a = self.0.iter().map(|x| $crate::ToF64::to_f64(*x)).collect();
+                $crate::linalg::lu(a, $row, $crate::Pivoting::Partial).map(|lu| (lu.determinant(), lu.swaps()))
+            }
+
+            // Solves `self * x = b` by Gaussian elimination in f64, choosing pivots as `strategy`
+            // specifies. Returns `None` when `linalg::lu` rejects a pivot as zero, which for
+            // partial and full pivoting means the matrix is singular. See `Pivoting` for the
+            // stability tradeoff.
+            #[allow(dead_code)]
+            $vis fn solve_with_pivoting(&self, b: &[f64], strategy: $crate::Pivoting) -> Option<Vec<f64>>
+                where for<'a> $t: $crate::ToF64
+            {
+                assert!($row == $col, "solve_with_pivoting requires a square matrix");
+                assert_eq!(b.len(), $row);
+                let a = self.0.iter().map(|x| $crate::ToF64::to_f64(*x)).collect();
+                $crate::linalg::lu(a, $row, strategy).map(|lu| lu.solve(b))
+            }
+
+            // Computes the orthogonal polar factor, the closest orthogonal matrix in the Frobenius
+            // norm, with the Newton-Schulz iteration `X <- X * (3I - X^T X) / 2` in f64. It
+            // converges when every singular value lies in (0, sqrt(3)), which covers rotation
+            // matrices that have drifted through accumulated rounding, and stops once `X^T X` is
+            // within 1e-12 of the identity or after 100 iterations.
+            #[allow(dead_code)]
+            $vis fn nearest_orthogonal(&self) -> $st
+                where for<'a> $t: $crate::ToF64 + $crate::FromF64
+            {
+                assert!($row == $col, "nearest_orthogonal requires a square matrix");
+                let n = $row;
+                let mut x: Vec<f64> = self.0.iter().map(|v| $crate::ToF64::to_f64(*v)).collect();
+                for _ in 0..100 {
+                    // xtx = X^T X
+                    let mut xtx = vec![0f64; n * n];
+                    for i in 0..n {
+                        for j in 0..n {
+                            for k in 0..n {
+                                xtx[i * n + j] += x[k * n + i] * x[k * n + j];
+                            }
+                        }
+                    }
+                    let mut error = 0f64;
+                    for i in 0..n {
+                        for j in 0..n {
+                            let target = if i == j { 1. } else { 0. };
+                            error = error.max((xtx[i * n + j] - target).abs());
+                        }
+                    }
+                    if error <= 1e-12 {
+                        break;
+                    }
+
+                    let mut next = vec![0f64; n * n];
+                    for i in 0..n {
+                        for j in 0..n {
+                            for k in 0..n {
+                                let factor = if k == j { 3. } else { 0. } - xtx[k * n + j];
+                                next[i * n + j] += 0.5 * x[i * n + k] * factor;
+                            }
+                        }
+                    }
+                    x = next;
+                }
+
+                let mut m = $st(self.0);
+                for (i, v) in x.iter().enumerate() {
+                    m.0[i] = <$t as $crate::FromF64>::from_f64(*v);
+                }
+                m
+            }
+
+            // Computes the permanent by expanding along each row over the unused columns.
+            // This takes exponential time and is only intended for small matrices (n <= 8).
+            #[allow(dead_code)]
+            $vis fn permanent(&self) -> $t {
+                assert!($row == $col, "permanent requires a square matrix");
+                fn expand(m: &$st, i: usize, used: usize) -> $t {
+                    if i == $row {
+                        return <$t as $crate::One>::one();
+                    }
+                    let mut sum = <$t as $crate::Zero>::zero();
+                    for j in 0..$col {
+                        if used & (1 << j) == 0 {
+                            sum += m[(i, j)] * expand(m, i + 1, used | (1 << j));
+                        }
+                    }
+                    sum
+                }
+                expand(self, 0, 0)
+            }
+
+            // Solves `self * x = b` for a tridiagonal matrix with the O(n) Thomas algorithm.
+            // Returns `None` if the matrix is not tridiagonal or a zero pivot is reached. The
+            // algorithm does not pivot, so it is meant for diagonally dominant systems.
+            #[allow(dead_code)]
+            $vis fn solve_tridiagonal(&self, b: &[$t]) -> Option<Vec<$t>> {
+                assert!($row == $col, "solve_tridiagonal requires a square matrix");
+                assert_eq!(b.len(), $row);
+                for i in 0..$row {
+                    for j in 0..$col {
+                        if (i > j + 1 || j > i + 1) && self[(i, j)] != <$t as $crate::Zero>::zero() {
+                            return None;
+                        }
+                    }
+                }
+
+                // Forward sweep, storing the modified upper band in `c` and right side in `d`.
+                let mut c = vec![<$t as $crate::Zero>::zero(); $row];
+                let mut d = vec![<$t as $crate::Zero>::zero(); $row];
+                for i in 0..$row {
+                    let (lower, prev_c, prev_d) = if i > 0 {
+                        (self[(i, i - 1)], c[i - 1], d[i - 1])
+                    } else {
+                        (<$t as $crate::Zero>::zero(), <$t as $crate::Zero>::zero(), <$t as $crate::Zero>::zero())
+                    };
+                    let denom = self[(i, i)] - lower * prev_c;
+                    if denom == <$t as $crate::Zero>::zero() {
+                        return None;
+                    }
+                    if i + 1 < $col {
+                        c[i] = self[(i, i + 1)] / denom;
+                    }
+                    d[i] = (b[i] - lower * prev_d) / denom;
+                }
+
+                // Back substitution leaves the solution in `d`.
+                for i in (0..d.len().saturating_sub(1)).rev() {
+                    let next = d[i + 1];
+                    d[i] -= c[i] * next;
+                }
+                Some(d)
+            }
+
+            // Iterates over the rows as slices of length `$col`, top to bottom.
+            #[allow(dead_code)]
+            $vis fn rows(&self) -> ::std::slice::ChunksExact<'_, $t> {
+                self.0.chunks_exact($col)
+            }
+
+            // Iterates over the rows as mutable slices of length `$col`, top to bottom.
+            #[allow(dead_code)]
+            $vis fn iter_rows_mut(&mut self) -> ::std::slice::ChunksMut<'_, $t> {
+                self.0.chunks_mut($col)
+            }
+
+            // Estimates the spectral radius, the largest absolute value of the eigenvalues, with
+            // `iters` steps of power iteration in f64. This is only an estimate: its accuracy
+            // depends on the iteration count and on the gap between the two largest eigenvalues,
+            // and it may not settle when several eigenvalues share the largest absolute value.
+            #[allow(dead_code)]
+            $vis fn spectral_radius(&self, iters: usize) -> f64
+                where for<'a> $t: $crate::ToF64
+            {
+                assert!($row == $col, "spectral_radius requires a square matrix");
+                let n = $row;
+                let a: Vec<f64> = self.0.iter().map(|x| $crate::ToF64::to_f64(*x)).collect();
+                // An uneven start is unlikely to be orthogonal to the dominant eigenvector.
+                let mut v: Vec<f64> = (0..n).map(|i| (i + 1) as f64).collect();
+                let mut radius = 0.;
+                for _ in 0..iters {
+                    let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+                    if norm == 0. {
+                        return 0.;
+                    }
+                    let mut w = vec![0f64; n];
+                    for i in 0..n {
+                        for j in 0..n {
+                            w[i] += a[i * n + j] * v[j] / norm;
+                        }
+                    }
+                    radius = w.iter().map(|x| x * x).sum::<f64>().sqrt();
+                    v = w;
+                }
+                radius
+            }
+
+            // Creates the permutation matrix with a 1 at `(i, perm[i])` for every row, so that
+            // row `i` of `P * A` is row `perm[i]` of `A`. Panics unless `perm` contains every
+            // index in `0..$row` exactly once.
+            #[allow(dead_code)]
+            $vis fn permutation(perm: &[usize]) -> $st {
+                assert!($row == $col, "permutation requires a square matrix");
+                assert_eq!(perm.len(), $row, "permutation must have one entry per row");
+                let mut seen = [false; $row];
+                let mut m = $st([<$t as $crate::Zero>::zero(); $row * $col]);
+                for (i, &p) in perm.iter().enumerate() {
+                    assert!(p < $row && !seen[p], "{:?} is not a permutation of 0..{}", perm, $row);
+                    seen[p] = true;
+                    m[(i, p)] = <$t as $crate::One>::one();
+                }
+                m
+            }
+
+            // Creates the Vandermonde matrix with `nodes[i]^j` at `(i, j)`, for fitting a
+            // polynomial of degree `degree` through the nodes. The shape is fixed by the type, so
+            // this panics unless there is one node per row and `degree + 1` equals `$col`.
+            #[allow(dead_code)]
+            $vis fn vandermonde(nodes: &[$t], degree: usize) -> $st {
+                assert_eq!(nodes.len(), $row, "vandermonde needs one node per row");
+                assert_eq!(degree + 1, $col, "vandermonde needs degree + 1 columns");
+                let mut m = $st([<$t as $crate::Zero>::zero(); $row * $col]);
+                for (i, &x) in nodes.iter().enumerate() {
+                    m[(i, 0)] = <$t as $crate::One>::one();
+                    for j in 1..$col {
+                        m[(i, j)] = m[(i, j - 1)] * x;
+                    }
+                }
+                m
+            }
+
+            // Creates the discrete Fourier transform matrix, with `exp(-2πi jk / n)` at `(j, k)`.
+            // There is no complex element type, so it is returned as its real and imaginary parts.
+            // Multiplying a real signal by each part gives the real and imaginary parts of its
+            // transform. Panics unless the matrix is square.
+            #[allow(dead_code)]
+            $vis fn dft() -> ($st, $st)
+                where for<'a> $t: $crate::FromF64
+            {
+                assert!($row == $col, "dft requires a square matrix");
+                let (mut re, mut im) = ($st::identity(), $st::identity());
+                for j in 0..$row {
+                    for k in 0..$col {
+                        // reducing jk mod n first keeps the angle, and so its rounding error, small
+                        let angle = -2. * ::std::f64::consts::PI * ((j * k) % $row) as f64 / $row as f64;
+                        re[(j, k)] = <$t as $crate::FromF64>::from_f64(angle.cos());
+                        im[(j, k)] = <$t as $crate::FromF64>::from_f64(angle.sin());
+                    }
+                }
+                (re, im)
+            }
+
+            // Sets the element at `pos`, the method form of `self[pos] = value`.
+            #[allow(dead_code)]
+            $vis fn set(&mut self, pos: (usize, usize), value: $t) {
+                self[pos] = value;
+            }
+
+            // Sets the element at `pos` like `set`, first pushing its previous value onto `log`
+            // so the edit can be reverted with `undo`.
+            #[allow(dead_code)]
+            $vis fn set_tracked(&mut self, pos: (usize, usize), value: $t, log: &mut Vec<((usize, usize), $t)>) {
+                log.push((pos, self[pos]));
+                self[pos] = value;
+            }
+
+            // Reverts every edit recorded in `log`, most recent first, leaving `log` empty.
+            #[allow(dead_code)]
+            $vis fn undo(&mut self, log: &mut Vec<((usize, usize), $t)>) {
+                while let Some((pos, previous)) = log.pop() {
+                    self[pos] = previous;
+                }
+            }
+
+            // Checks whether `self * other` and `other * self` agree within `eps` in every
+            // element. Both products are computed in f64.
+            #[allow(dead_code)]
+            $vis fn commutes_with(&self, other: &$st, eps: f64) -> bool
+                where for<'a> $t: $crate::ToF64
+            {
+                assert!($row == $col, "commutes_with requires a square matrix");
+                for i in 0..$row {
+                    for j in 0..$col {
+                        let (mut ab, mut ba) = (0., 0.);
+                        for k in 0..$col {
+                            ab += $crate::ToF64::to_f64(self[(i, k)]) * $crate::ToF64::to_f64(other[(k, j)]);
+                            ba += $crate::ToF64::to_f64(other[(i, k)]) * $crate::ToF64::to_f64(self[(k, j)]);
+                        }
+                        if (ab - ba).abs() > eps {
+                            return false;
+                        }
+                    }
+                }
+                true
+            }
+
+            // Computes the commutator `self * other - other * self`, which is zero exactly when
+            // the two matrices commute.
+            #[allow(dead_code)]
+            $vis fn commutator(&self, other: &$st) -> $st {
+                assert!($row == $col, "commutator requires a square matrix");
+                let mut result = $st([<$t as $crate::Zero>::zero(); $row * $col]);
+                for i in 0..$row {
+                    for j in 0..$col {
+                        let mut sum = <$t as $crate::Zero>::zero();
+                        for k in 0..$col {
+                            sum += self[(i, k)] * other[(k, j)];
+                            sum -= other[(i, k)] * self[(k, j)];
+                        }
+                        result[(i, j)] = sum;
+                    }
+                }
+                result
+            }
+
+            // Divides every element by the sum of all elements, so that a nonnegative matrix
+            // becomes a joint probability distribution summing to one. An all-zero matrix has
+            // no total to divide by and becomes the uniform distribution instead. Meant for float
+            // element types, since integer division truncates.
+            #[allow(dead_code)]
+            $vis fn to_joint_distribution(&self) -> $st {
+                let zero = <$t as $crate::Zero>::zero();
+                let one = <$t as $crate::One>::one();
+                let total = self.0.iter().fold(zero, |acc, x| acc + *x);
+                if total == zero {
+                    let count = self.0.iter().fold(zero, |n, _| n + one);
+                    return $st([one / count; $row * $col]);
+                }
+                self.map_indexed(|_, x| x / total)
+            }
+
+            // Computes the Shannon entropy in bits, `-sum(p * log2(p))`, treating the elements as
+            // one flattened probability distribution. Zero elements are skipped, following the
+            // convention `0 * log2(0) = 0`. See `to_joint_distribution` to normalize first.
+            #[allow(dead_code)]
+            $vis fn entropy(&self) -> f64
+                where for<'a> $t: $crate::ToF64
+            {
+                self.0.iter()
+                    .map(|x| $crate::ToF64::to_f64(*x))
+                    .filter(|p| *p != 0.)
+                    .map(|p| -p * p.log2())
+                    .sum()
+            }
+
+            // Computes the graph Laplacian `D - A` of an adjacency matrix, where `D` is the
+            // diagonal matrix of row sums (vertex degrees). Every row of the result sums to zero.
+            #[allow(dead_code)]
+            $vis fn laplacian(&self) -> $st {
+                assert!($row == $col, "laplacian requires a square matrix");
+                let zero = <$t as $crate::Zero>::zero();
+                let mut result = self.map_indexed(|_, x| zero - x);
+                for i in 0..$row {
+                    let degree = (0..$col).fold(zero, |acc, j| acc + self[(i, j)]);
+                    result[(i, i)] += degree;
+                }
+                result
+            }
+
+            // Transposes every matrix in the slice in place. Each transpose is independent of
+            // the others, so callers can split the slice up to process it in parallel.
+            #[allow(dead_code)]
+            $vis fn transpose_all(matrices: &mut [$st]) {
+                assert!($row == $col, "transpose_all requires a square matrix");
+                for m in matrices.iter_mut() {
+                    m.transpose_mut();
+                }
+            }
+
+            // Counts the singular values greater than `tol`, which is more robust than counting
+            // pivots when the data carries rounding or measurement noise. The singular values
+            // are the column norms left by one-sided Jacobi (Hestenes) orthogonalization in f64,
+            // which rotates pairs of columns until all of them are orthogonal to each other.
+            #[allow(dead_code)]
+            $vis fn numerical_rank(&self, tol: f64) -> usize
+                where for<'a> $t: $crate::ToF64
+            {
+                let (m, n) = ($row, $col);
+                let mut u: Vec<f64> = self.0.iter().map(|x| $crate::ToF64::to_f64(*x)).collect();
+                for _ in 0..60 {
+                    let mut rotated = false;
+                    for p in 0..n {
+                        for q in p + 1..n {
+                            let (mut alpha, mut beta, mut gamma) = (0f64, 0f64, 0f64);
+                            for i in 0..m {
+                                alpha += u[i * n + p] * u[i * n + p];
+                                beta += u[i * n + q] * u[i * n + q];
+                                gamma += u[i * n + p] * u[i * n + q];
+                            }
+                            if gamma.abs() <= 1e-15 * (alpha * beta).sqrt() {
+                                continue;
+                            }
+                            rotated = true;
+                            let zeta = (beta - alpha) / (2. * gamma);
+                            let t = zeta.signum() / (zeta.abs() + (1. + zeta * zeta).sqrt());
+                            let c = 1. / (1. + t * t).sqrt();
+                            let s = c * t;
+                            for i in 0..m {
+                                let (up, uq) = (u[i * n + p], u[i * n + q]);
+                                u[i * n + p] = c * up - s * uq;
+                                u[i * n + q] = s * up + c * uq;
+                            }
+                        }
+                    }
+                    if !rotated {
+                        break;
+                    }
+                }
+                (0..n)
+                    .map(|j| (0..m).map(|i| u[i * n + j] * u[i * n + j]).sum::<f64>().sqrt())
+                    .filter(|sigma| *sigma > tol)
+                    .count()
+            }
+
+            // Diagonalizes a symmetric matrix with the cyclic Jacobi eigenvalue algorithm in f64,
+            // returning the eigenvalues in descending order and a matrix whose columns are the
+            // matching orthonormal eigenvectors, so that `self = V * diag(values) * V^T`. Each
+            // rotation zeroes one off-diagonal pair; convergence is quadratic once the
+            // off-diagonal elements are small, and the sweeps stop when their sum of squares
+            // falls below 1e-24 of the total, or after 100 sweeps. Only the upper triangle is
+            // read. Meant for float element types, since the eigenvectors are converted back.
+            #[allow(dead_code)]
+            $vis fn eigen_symmetric(&self) -> (Vec<f64>, $st)
+                where for<'a> $t: $crate::ToF64 + $crate::FromF64
+            {
+                assert!($row == $col, "eigen_symmetric requires a square matrix");
+                let n = $row;
+                let mut a = vec![0f64; n * n];
+                for i in 0..n {
+                    for j in i..n {
+                        let x = $crate::ToF64::to_f64(self[(i, j)]);
+                        a[i * n + j] = x;
+                        a[j * n + i] = x;
+                    }
+                }
+                let (values, v) = $crate::linalg::symmetric_eigen(a, n);
+                let mut vectors = $st(self.0);
+                for (x, e) in vectors.0.iter_mut().zip(v.iter()) {
+                    *x = <$t as $crate::FromF64>::from_f64(*e);
+                }
+                (values, vectors)
+            }
+
+            // Principal component analysis of a data matrix with one sample per row and one
+            // feature per column. Each column is centered on its mean, the sample covariance
+            // `X^T X / (rows - 1)` is diagonalized, and the `k` largest eigenvalues are returned
+            // in descending order with their unit-length principal directions, one `Vec` of
+            // `$col` weights per component. The sign of each direction is arbitrary.
+            #[allow(dead_code)]
+            $vis fn pca(&self, k: usize) -> (Vec<f64>, Vec<Vec<f64>>)
+                where for<'a> $t: $crate::ToF64
+            {
+                assert!($row > 1, "pca needs at least two samples");
+                assert!(k <= $col, "pca cannot return more components than features");
+                let (m, n) = ($row, $col);
+                let mut x: Vec<f64> = self.0.iter().map(|v| $crate::ToF64::to_f64(*v)).collect();
+                for j in 0..n {
+                    let mean = (0..m).map(|i| x[i * n + j]).sum::<f64>() / m as f64;
+                    for i in 0..m {
+                        x[i * n + j] -= mean;
+                    }
+                }
+                let mut cov = vec![0f64; n * n];
+                for a in 0..n {
+                    for b in 0..n {
+                        let dot: f64 = (0..m).map(|i| x[i * n + a] * x[i * n + b]).sum();
+                        cov[a * n + b] = dot / (m - 1) as f64;
+                    }
+                }
+
+                let (mut values, v) = $crate::linalg::symmetric_eigen(cov, n);
+                values.truncate(k);
+                let components = (0..k).map(|c| (0..n).map(|i| v[i * n + c]).collect()).collect();
+                (values, components)
+            }
+
+            // Computes the determinant in the element type. Up to 3x3 this expands by cofactors;
+            // larger matrices use fraction-free (Bareiss) Gaussian elimination, whose divisions
+            // are exact, so integer matrices get exact results barring overflow.
+            #[allow(dead_code)]
+            $vis fn determinant(&self) -> $t {
+                assert!($row == $col, "determinant requires a square matrix, got {}x{}", $row, $col);
+                let zero = <$t as $crate::Zero>::zero();
+                let one = <$t as $crate::One>::one();
+                let m = self;
+                match $row {
+                    0 => return one,
+                    1 => return m[(0, 0)],
+                    2 => return m[(0, 0)] * m[(1, 1)] - m[(0, 1)] * m[(1, 0)],
+                    3 => {
+                        return m[(0, 0)] * (m[(1, 1)] * m[(2, 2)] - m[(1, 2)] * m[(2, 1)])
+                            - m[(0, 1)] * (m[(1, 0)] * m[(2, 2)] - m[(1, 2)] * m[(2, 0)])
+                            + m[(0, 2)] * (m[(1, 0)] * m[(2, 1)] - m[(1, 1)] * m[(2, 0)]);
+                    }
+                    _ => {}
+                }
+
+                let n = $row;
+                let mut a = $st(self.0);
+                let mut negate = false;
+                let mut previous = one;
+                for k in 0..n - 1 {
+                    if a[(k, k)] == zero {
+                        match (k + 1..n).find(|&r| a[(r, k)] != zero) {
+                            Some(r) => {
+                                for j in 0..n {
+                                    a.swap((k, j), (r, j));
+                                }
+                                negate = !negate;
+                            }
+                            None => return zero,
+                        }
+                    }
+                    for i in k + 1..n {
+                        for j in k + 1..n {
+                            a[(i, j)] = (a[(i, j)] * a[(k, k)] - a[(i, k)] * a[(k, j)]) / previous;
+                        }
+                    }
+                    previous = a[(k, k)];
+                }
+                let det = a[(n - 1, n - 1)];
+                if negate { zero - det } else { det }
+            }
+
+            // Computes the determinant of a 4x4 matrix by Laplace expansion along the top two
+            // rows. Each of the six 2x2 minors of the bottom two rows is computed once and paired
+            // with the complementary minor of the top two rows, instead of being recomputed in
+            // every 3x3 cofactor.
+            #[allow(dead_code)]
+            $vis fn determinant_4x4(&self) -> $t {
+                assert!($row == 4 && $col == 4, "determinant_4x4 requires a 4x4 matrix, got {}x{}", $row, $col);
+                let m = self;
+                let top = |a: usize, b: usize| m[(0, a)] * m[(1, b)] - m[(0, b)] * m[(1, a)];
+                let bottom = |a: usize, b: usize| m[(2, a)] * m[(3, b)] - m[(2, b)] * m[(3, a)];
+                let (b01, b02, b03) = (bottom(0, 1), bottom(0, 2), bottom(0, 3));
+                let (b12, b13, b23) = (bottom(1, 2), bottom(1, 3), bottom(2, 3));
+                top(0, 1) * b23 - top(0, 2) * b13 + top(0, 3) * b12
+                    + top(1, 2) * b03 - top(1, 3) * b02 + top(2, 3) * b01
+            }
+
+            // Raises a symmetric matrix to a real power as `V * diag(values^p) * V^T`, using the
+            // eigendecomposition from `eigen_symmetric`. Eigenvalues within `f64::EPSILON` times
+            // the largest one times the size are rounding noise and count as zero, so singular
+            // positive semidefinite matrices have fractional powers. Returns `None` when the
+            // result is not real or finite: a negative eigenvalue with a fractional `p`, or a zero
+            // eigenvalue with a negative `p`. For symmetric positive definite matrices, `p = 0.5`
+            // gives the principal square root and `p = -0.5` its inverse.
+            #[allow(dead_code)]
+            $vis fn powf_matrix(&self, p: f64) -> Option<$st>
+                where for<'a> $t: $crate::ToF64 + $crate::FromF64
+            {
+                assert!($row == $col, "powf_matrix requires a square matrix");
+                let n = $row;
+                let (values, v) = self.eigen_symmetric();
+                let scale = values.iter().fold(0f64, |m, x| m.max(x.abs()));
+                let tolerance = f64::EPSILON * scale * n as f64;
+                let mut powers = Vec::with_capacity(n);
+                for value in values {
+                    let value = if value.abs() <= tolerance { 0. } else { value };
+                    if (value < 0. && p.fract() != 0.) || (value == 0. && p < 0.) {
+                        return None;
+                    }
+                    powers.push(value.powf(p));
+                }
+
+                let mut result = $st(self.0);
+                for i in 0..n {
+                    for j in 0..n {
+                        let x: f64 = (0..n)
+                            .map(|k| $crate::ToF64::to_f64(v[(i, k)]) * powers[k] * $crate::ToF64::to_f64(v[(j, k)]))
+                            .sum();
+                        result[(i, j)] = <$t as $crate::FromF64>::from_f64(x);
+                    }
+                }
+                Some(result)
+            }
+
+            // Inverts the matrix in f64 through the LU factorization from `linalg::lu`, which
+            // pivots on the largest remaining element of each column. Returns `None` when the
+            // matrix is singular. Debug builds panic if `self * inv`, in the element type, is
+            // further from the identity than rounding allows; see `linalg::check_solution`.
+            // Meant for float element types.
+            #[allow(dead_code)]
+            $vis fn inverse(&self) -> Option<$st>
+                where for<'a> $t: $crate::ToF64 + $crate::FromF64
+            {
+                self.inverse_and_det().map(|(inv, _)| inv)
+            }
+
+            // Like `inverse`, but also returns the determinant, which comes from the pivots of
+            // the same factorization.
+            #[allow(dead_code)]
+            $vis fn inverse_and_det(&self) -> Option<($st, f64)>
+                where for<'a> $t: $crate::ToF64 + $crate::FromF64
+            {
+                assert!($row == $col, "inverse_and_det requires a square matrix");
+                let n = $row;
+                let a = self.0.iter().map(|x| $crate::ToF64::to_f64(*x)).collect();
+                let lu = $crate::linalg::lu(a, n, $crate::Pivoting::Partial)?;
+
+                let mut inv = $st(self.0);
+                let mut e = vec![0f64; n];
+                for j in 0..n {
+                    e[j] = 1.;
+                    for (i, x) in lu.solve(&e).into_iter().enumerate() {
+                        inv[(i, j)] = <$t as $crate::FromF64>::from_f64(x);
+                    }
+                    e[j] = 0.;
+                }
+
+                #[cfg(debug_assertions)]
+                {
+                    let to_f64 = |m: &$st| -> Vec<f64> { m.0.iter().map(|x| $crate::ToF64::to_f64(*x)).collect() };
+                    let identity: Vec<f64> = (0..n * n).map(|k| if k % (n + 1) == 0 { 1. } else { 0. }).collect();
+                    $crate::linalg::check_solution("inverse", &to_f64(self), &to_f64(&inv), &identity, n,
+                        $crate::linalg::roundoff(|x| $crate::ToF64::to_f64(<$t as $crate::FromF64>::from_f64(x))));
+                }
+                Some((inv, lu.determinant()))
+            }
+
+            // Serializes to a compact binary format: the row and column counts as little-endian
+            // u32s and the `ByteElement::TAG` of the element type as one byte, followed by the
+            // elements in row-major order as little-endian bytes.
+            #[allow(dead_code)]
+            $vis fn to_bytes(&self) -> Vec<u8>
+                where for<'a> $t: $crate::ByteElement
+            {
+                let size = <$t as $crate::ByteElement>::SIZE;
+                let mut bytes = Vec::with_capacity(9 + size * $row * $col);
+                bytes.extend_from_slice(&($row as u32).to_le_bytes());
+                bytes.extend_from_slice(&($col as u32).to_le_bytes());
+                bytes.push(<$t as $crate::ByteElement>::TAG);
+                for x in self.0.iter() {
+                    $crate::ByteElement::write_le(x, &mut bytes);
+                }
+                bytes
+            }
+
+            // Deserializes the format written by `to_bytes`, checking that the header matches
+            // the shape and element type of this matrix type and that no bytes are missing or
+            // left over.
+            #[allow(dead_code)]
+            $vis fn from_bytes(bytes: &[u8]) -> Result<$st, $crate::ParseError>
+                where for<'a> $t: $crate::ByteElement
+            {
+                let size = <$t as $crate::ByteElement>::SIZE;
+                let expected = 9 + size * $row * $col;
+                if bytes.len() < 9 {
+                    return Err($crate::ParseError::ByteLength { expected, found: bytes.len() });
+                }
+                let read_u32 = |at: usize| {
+                    let mut buf = [0u8; 4];
+                    buf.copy_from_slice(&bytes[at..at + 4]);
+                    u32::from_le_bytes(buf) as usize
+                };
+                let (rows, cols, tag) = (read_u32(0), read_u32(4), bytes[8]);
+                if rows != $row {
+                    return Err($crate::ParseError::RowCount { expected: $row, found: rows });
+                }
+                if cols != $col {
+                    return Err($crate::ParseError::ColumnCount { expected: $col, found: cols });
+                }
+                if tag != <$t as $crate::ByteElement>::TAG {
+                    return Err($crate::ParseError::ElementType {
+                        expected: <$t as $crate::ByteElement>::TAG,
+                        found: tag,
+                    });
+                }
+                if bytes.len() != expected {
+                    return Err($crate::ParseError::ByteLength { expected, found: bytes.len() });
+                }
+                $st::try_from_fn(|(i, j)| {
+                    let at = 9 + (i * $col + j) * size;
+                    Ok($crate::ByteElement::read_le(&bytes[at..at + size]))
+                })
+            }
+
+            // Lists the positions of NaN and infinite elements in row-major order, to locate
+            // where a diverging computation first blew up.
+            #[allow(dead_code)]
+            $vis fn find_non_finite(&self) -> Vec<(usize, usize)>
+                where for<'a> $t: $crate::ToF64
+            {
+                let mut found = Vec::new();
+                for (i, x) in self.0.iter().enumerate() {
+                    if !$crate::ToF64::to_f64(*x).is_finite() {
+                        found.push((i / $col, i % $col));
+                    }
+                }
+                found
+            }
+
+            // Checks whether any element is NaN or infinite.
+            #[allow(dead_code)]
+            $vis fn has_non_finite(&self) -> bool
+                where for<'a> $t: $crate::ToF64
+            {
+                self.0.iter().any(|x| !$crate::ToF64::to_f64(*x).is_finite())
+            }
+
+            // Adds `alpha * other` to this matrix in place, the matrix form of BLAS axpy.
+            #[allow(dead_code)]
+            $vis fn axpy(&mut self, alpha: $t, other: &$st) {
+                for (x, y) in self.0.iter_mut().zip(other.0.iter()) {
+                    *x += alpha * *y;
+                }
+            }
+
+            // Replaces every element `x` with `x * scale + addend` in a single pass, equivalent
+            // to `*self *= scale; *self += addend`.
+            #[allow(dead_code)]
+            $vis fn scale_add_assign(&mut self, scale: $t, addend: $t) {
+                for x in self.0.iter_mut() {
+                    *x = *x * scale + addend;
+                }
+            }
+
+            // Embeds the matrix in homogeneous coordinates, one dimension larger, as nested row
+            // vectors that can be passed to `from_rows_vec` of a `($row + 1) x ($col + 1)`
+            // matrix type. The matrix fills the top left block, the new last row and last column
+            // are zero, and the bottom right corner is one, so that a translation can later be
+            // written into the last column.
+            #[allow(dead_code)]
+            $vis fn to_homogeneous(&self) -> Vec<Vec<$t>> {
+                let zero = <$t as $crate::Zero>::zero();
+                let mut rows: Vec<Vec<$t>> = self.0.chunks($col)
+                    .map(|row| {
+                        let mut row = row.to_vec();
+                        row.push(zero);
+                        row
+                    })
+                    .collect();
+                let mut last = vec![zero; $col];
+                last.push(<$t as $crate::One>::one());
+                rows.push(last);
+                rows
+            }
+
+            // Computes the rank as the number of pivots found by row reduction in f64, treating
+            // elements within rounding error of zero as zero. See `numerical_rank` for noisy data.
+            #[allow(dead_code)]
+            $vis fn rank(&self) -> usize
+                where for<'a> $t: $crate::ToF64
+            {
+                let mut a: Vec<f64> = self.0.iter().map(|x| $crate::ToF64::to_f64(*x)).collect();
+                $crate::linalg::rref(&mut a, $row, $col).len()
+            }
+
+            // Computes the nullity, the dimension of the null space, as `$col - rank()`.
+            #[allow(dead_code)]
+            $vis fn nullity(&self) -> usize
+                where for<'a> $t: $crate::ToF64
+            {
+                $col - self.rank()
+            }
+
+            // Computes a basis of the null space from the reduced row echelon form in f64, with
+            // one vector of length `$col` per free column. The vector for free column `f` has a
+            // one at `f`, zeros at the other free columns, and the values solving `self * x = 0`
+            // at the pivot columns. The basis is ordered by free column and is not orthonormal.
+            #[allow(dead_code)]
+            $vis fn null_space(&self) -> Vec<Vec<f64>>
+                where for<'a> $t: $crate::ToF64
+            {
+                let mut a: Vec<f64> = self.0.iter().map(|x| $crate::ToF64::to_f64(*x)).collect();
+                let pivots = $crate::linalg::rref(&mut a, $row, $col);
+                (0..$col)
+                    .filter(|c| !pivots.contains(c))
+                    .map(|free| {
+                        let mut v = vec![0f64; $col];
+                        v[free] = 1.;
+                        for (r, &p) in pivots.iter().enumerate() {
+                            v[p] = -a[r * $col + free];
+                        }
+                        v
+                    })
+                    .collect()
+            }
+
+            // Computes the Moore-Penrose pseudo-inverse `(A^T A)^-1 A^T` in f64, which has the
+            // transposed shape and gives least squares solutions of overdetermined systems as
+            // `x = A+ b`. This formula needs full column rank, so `None` is returned when
+            // `linalg::lu` finds `A^T A` singular. Its tolerance is relative to the largest
+            // element of `A^T A`, so the answer does not depend on the scale of `A`.
+            // Rank-deficient matrices need an SVD-based pseudo-inverse instead. The result is a
+            // `$tr`, so a non-square matrix type needs a `transpose:` companion, and panics
+            // without one. Meant for float element types.
+            #[allow(dead_code)]
+            $vis fn pseudo_inverse(&self) -> Option<$tr>
+                where for<'a> $t: $crate::ToF64 + $crate::FromF64
+            {
+                let (m, n) = ($row, $col);
+                let a: Vec<f64> = self.0.iter().map(|x| $crate::ToF64::to_f64(*x)).collect();
+                let mut ata = vec![0f64; n * n];
+                for i in 0..n {
+                    for j in 0..n {
+                        ata[i * n + j] = (0..m).map(|k| a[k * n + i] * a[k * n + j]).sum();
+                    }
+                }
+                let lu = $crate::linalg::lu(ata, n, $crate::Pivoting::Partial)?;
+
+                let mut result = $tr::zeros();
+                assert_eq!(result.size(), (n, m),
+                    "pseudo_inverse of a non-square matrix needs a `transpose:` companion type");
+                // Column j of the pseudo-inverse solves A^T A x = (row j of A)^T.
+                for j in 0..m {
+                    for (i, x) in lu.solve(&a[j * n..(j + 1) * n]).into_iter().enumerate() {
+                        result[(i, j)] = <$t as $crate::FromF64>::from_f64(x);
+                    }
+                }
+                Some(result)
+            }
+
+            // Snaps a 3x3 rotation to the closest of the 24 axis-aligned rotations, the signed
+            // permutation matrices with determinant +1, measured by Frobenius distance. All
+            // candidates have the same norm, so the closest is the one with the largest
+            // element-wise inner product with `self`.
+            #[allow(dead_code)]
+            $vis fn nearest_axis_aligned(&self) -> $st
+                where for<'a> $t: $crate::ToF64
+            {
+                assert!($row == 3 && $col == 3, "nearest_axis_aligned requires a 3x3 matrix");
+                // Permutations of the columns, each with the sign of the permutation.
+                let perms = [
+                    ([0, 1, 2], 1.), ([1, 2, 0], 1.), ([2, 0, 1], 1.),
+                    ([0, 2, 1], -1.), ([2, 1, 0], -1.), ([1, 0, 2], -1.),
+                ];
+                let mut best = ([0, 1, 2], [1., 1., 1.]);
+                let mut best_score = f64::NEG_INFINITY;
+                for &(perm, parity) in perms.iter() {
+                    for bits in 0..8 {
+                        let signs = [
+                            if bits & 1 == 0 { 1. } else { -1. },
+                            if bits & 2 == 0 { 1. } else { -1. },
+                            if bits & 4 == 0 { 1. } else { -1. },
+                        ];
+                        if parity * signs[0] * signs[1] * signs[2] < 0. {
+                            continue;
+                        }
+                        let score: f64 = (0..3)
+                            .map(|i| signs[i] * $crate::ToF64::to_f64(self[(i, perm[i])]))
+                            .sum();
+                        if score > best_score {
+                            best_score = score;
+                            best = (perm, signs);
+                        }
+                    }
+                }
+
+                let zero = <$t as $crate::Zero>::zero();
+                let one = <$t as $crate::One>::one();
+                let mut m = $st::zeros();
+                for i in 0..3 {
+                    m[(i, best.0[i])] = if best.1[i] > 0. { one } else { zero - one };
+                }
+                m
+            }
+
+            // Converts a 3x3 rotation matrix to a unit quaternion `[w, x, y, z]`.
+            // The branch is picked by the trace and the largest diagonal entry so
+            // that the square root is always taken of a value well away from zero.
+            #[allow(dead_code)]
+            $vis fn to_quaternion(&self) -> [f64; 4]
+                where for<'a> $t: $crate::ToF64
+            {
+                assert!($row == 3 && $col == 3, "to_quaternion requires a 3x3 matrix");
+                let m = |i: usize, j: usize| $crate::ToF64::to_f64(self[(i, j)]);
+                let trace = m(0, 0) + m(1, 1) + m(2, 2);
+                let q = if trace > 0. {
+                    let s = 2. * (trace + 1.).sqrt();
+                    [0.25 * s, (m(2, 1) - m(1, 2)) / s, (m(0, 2) - m(2, 0)) / s, (m(1, 0) - m(0, 1)) / s]
+                } else if m(0, 0) > m(1, 1) && m(0, 0) > m(2, 2) {
+                    let s = 2. * (1. + m(0, 0) - m(1, 1) - m(2, 2)).sqrt();
+                    [(m(2, 1) - m(1, 2)) / s, 0.25 * s, (m(0, 1) + m(1, 0)) / s, (m(0, 2) + m(2, 0)) / s]
+                } else if m(1, 1) > m(2, 2) {
+                    let s = 2. * (1. + m(1, 1) - m(0, 0) - m(2, 2)).sqrt();
+                    [(m(0, 2) - m(2, 0)) / s, (m(0, 1) + m(1, 0)) / s, 0.25 * s, (m(1, 2) + m(2, 1)) / s]
+                } else {
+                    let s = 2. * (1. + m(2, 2) - m(0, 0) - m(1, 1)).sqrt();
+                    [(m(1, 0) - m(0, 1)) / s, (m(0, 2) + m(2, 0)) / s, (m(1, 2) + m(2, 1)) / s, 0.25 * s]
+                };
+
+                // Keep w non-negative so the same rotation always gives the same quaternion.
+                let sign = if q[0] < 0. { -1. } else { 1. };
+                let norm = q.iter().map(|x| x * x).sum::<f64>().sqrt();
+                [sign * q[0] / norm, sign * q[1] / norm, sign * q[2] / norm, sign * q[3] / norm]
+            }
+
+            // Builds a 3x3 rotation matrix from a quaternion `[w, x, y, z]`. The
+            // quaternion is normalized first.
+            #[allow(dead_code)]
+            $vis fn from_quaternion(q: [f64; 4]) -> $st
+                where for<'a> $t: $crate::FromF64
+            {
+                assert!($row == 3 && $col == 3, "from_quaternion requires a 3x3 matrix");
+                let norm = q.iter().map(|x| x * x).sum::<f64>().sqrt();
+                assert!(norm > 0., "from_quaternion requires a non-zero quaternion");
+                let (w, x, y, z) = (q[0] / norm, q[1] / norm, q[2] / norm, q[3] / norm);
+                let values = [
+                    1. - 2. * (y * y + z * z), 2. * (x * y - w * z), 2. * (x * z + w * y),
+                    2. * (x * y + w * z), 1. - 2. * (x * x + z * z), 2. * (y * z - w * x),
+                    2. * (x * z - w * y), 2. * (y * z + w * x), 1. - 2. * (x * x + y * y),
+                ];
+                let mut m = $st::zeros();
+                for (cell, &v) in m.0.iter_mut().zip(values.iter()) {
+                    *cell = <$t as $crate::FromF64>::from_f64(v);
+                }
+                m
+            }
+
+            // Iterates over the elements of row `i`, left to right.
+            #[allow(dead_code)]
+            $vis fn row_iter(&self, i: usize) -> impl Iterator<Item = &$t> + '_ {
+                assert!(i < $row, "row index {} out of bounds for {} rows", i, $row);
+                self.0[i * $col..(i + 1) * $col].iter()
+            }
+
+            // Iterates over the elements of column `j`, top to bottom.
+            #[allow(dead_code)]
+            $vis fn col_iter(&self, j: usize) -> impl Iterator<Item = &$t> + '_ {
+                assert!(j < $col, "column index {} out of bounds for {} columns", j, $col);
+                self.0[j..].iter().step_by($col)
+            }
+
+            // Checks whether the matrix is orthogonal, meaning the columns are orthonormal and
+            // every entry of `transpose * self` is within `eps` of the identity.
+            #[allow(dead_code)]
+            $vis fn is_orthogonal(&self, eps: f64) -> bool
+                where for<'a> $t: $crate::ToF64
+            {
+                if $row != $col {
+                    return false;
+                }
+                for a in 0..$col {
+                    for b in a..$col {
+                        let dot: f64 = (0..$row)
+                            .map(|k| $crate::ToF64::to_f64(self[(k, a)]) * $crate::ToF64::to_f64(self[(k, b)]))
+                            .sum();
+                        let expected = if a == b { 1. } else { 0. };
+                        if (dot - expected).abs() > eps {
+                            return false;
+                        }
+                    }
+                }
+                true
+            }
+
+            // Checks whether the matrix is a proper rotation: orthogonal within `eps` with
+            // determinant +1. Reflections are orthogonal too but have determinant -1.
+            #[allow(dead_code)]
+            $vis fn is_rotation(&self, eps: f64) -> bool
+                where for<'a> $t: $crate::ToF64
+            {
+                self.is_orthogonal(eps) && self.determinant_f64() > 0.
+            }
+
+            // Computes the Hadamard product, multiplying corresponding elements.
+            #[allow(dead_code)]
+            $vis fn hadamard(&self, other: &$st) -> $st {
+                let mut result = *self;
+                for (x, y) in result.0.iter_mut().zip(other.0.iter()) {
+                    *x *= *y;
+                }
+                result
+            }
+
+            // Orthonormalizes the columns with modified Gram-Schmidt in f64, left to right, so
+            // the result spans the same column space. A column that is linearly dependent on
+            // the ones before it, detected as a residual norm no larger than `f64::EPSILON`
+            // times the largest column norm times the size, is set to zero instead.
+            #[allow(dead_code)]
+            $vis fn orthonormalize_columns(&self) -> $st
+                where for<'a> $t: $crate::ToF64 + $crate::FromF64
+            {
+                let mut cols: Vec<Vec<f64>> = (0..$col)
+                    .map(|j| (0..$row).map(|i| $crate::ToF64::to_f64(self[(i, j)])).collect())
+                    .collect();
+                let norm = |v: &[f64]| v.iter().map(|x| x * x).sum::<f64>().sqrt();
+                let scale = cols.iter().map(|c| norm(c)).fold(0., f64::max);
+                let tolerance = f64::EPSILON * scale * ::std::cmp::max($row, $col) as f64;
+
+                for j in 0..$col {
+                    let (done, rest) = cols.split_at_mut(j);
+                    let v = &mut rest[0];
+                    for q in done.iter() {
+                        let dot: f64 = q.iter().zip(v.iter()).map(|(a, b)| a * b).sum();
+                        for (x, y) in v.iter_mut().zip(q.iter()) {
+                            *x -= dot * y;
+                        }
+                    }
+                    let n = norm(v);
+                    for x in v.iter_mut() {
+                        *x = if n > tolerance { *x / n } else { 0. };
+                    }
+                }
+
+                $st::from_fn(|(i, j)| <$t as $crate::FromF64>::from_f64(cols[j][i]))
+            }
+
+            // Computes the angle in radians between the two matrices treated as flattened
+            // vectors, from their Frobenius inner product and norms, in [0, pi]. A zero matrix
+            // has no direction; it is treated as orthogonal to everything, giving pi / 2.
+            #[allow(dead_code)]
+            $vis fn angle_with(&self, other: &$st) -> f64
+                where for<'a> $t: $crate::ToF64
+            {
+                let (mut dot, mut a, mut b) = (0., 0., 0.);
+                for (x, y) in self.0.iter().zip(other.0.iter()) {
+                    let (x, y) = ($crate::ToF64::to_f64(*x), $crate::ToF64::to_f64(*y));
+                    dot += x * y;
+                    a += x * x;
+                    b += y * y;
+                }
+                if a == 0. || b == 0. {
+                    return ::std::f64::consts::FRAC_PI_2;
+                }
+                // Rounding can push the cosine just outside [-1, 1].
+                (dot / (a.sqrt() * b.sqrt())).max(-1.).min(1.).acos()
+            }
+
+            // Adds up all elements, starting from zero. This shadows `Sum::sum` in path calls
+            // like `$st::sum(iter)`, so sum an iterator of matrices with `iter.sum()` or
+            // `Sum::sum(iter)` instead.
+            #[allow(dead_code)]
+            $vis fn sum(&self) -> $t {
+                self.0.iter().fold(<$t as $crate::Zero>::zero(), |acc, x| acc + *x)
+            }
+
+            // Multiplies two batches pairwise, returning `lhs[k] * rhs[k]` for every `k`. Both
+            // batches must have the same length and the matrix must be square.
+            #[allow(dead_code)]
+            $vis fn mul_batch(lhs: &[$st], rhs: &[$st]) -> Vec<$st> {
+                assert_eq!(lhs.len(), rhs.len(), "mul_batch requires batches of equal length");
+                assert!($row == $col, "mul_batch requires a square matrix, got {}x{}", $row, $col);
+                lhs.iter().zip(rhs.iter()).map(|(a, b)| *a * *b).collect()
+            }
+
+            // Finds the smallest element, or `None` for a matrix with no elements. The first of
+            // equal elements is returned, and elements that do not compare, such as NaN, are
+            // only returned when nothing before them is smaller.
+            #[allow(dead_code)]
+            $vis fn min(&self) -> Option<&$t>
+                where for<'a> $t: PartialOrd
+            {
+                self.0.iter().fold(None, |best, x| match best {
+                    Some(b) if x < b => Some(x),
+                    Some(b) => Some(b),
+                    None => Some(x),
+                })
+            }
+
+            // Finds the largest element, or `None` for a matrix with no elements. Ties and
+            // elements that do not compare are handled as in `min`.
+            #[allow(dead_code)]
+            $vis fn max(&self) -> Option<&$t>
+                where for<'a> $t: PartialOrd
+            {
+                self.0.iter().fold(None, |best, x| match best {
+                    Some(b) if x > b => Some(x),
+                    Some(b) => Some(b),
+                    None => Some(x),
+                })
+            }
+
+            // Averages the rows in f64, treating each row as a data point, giving the centroid
+            // as a vector of length `$col`.
+            #[allow(dead_code)]
+            $vis fn mean_row(&self) -> Vec<f64>
+                where for<'a> $t: $crate::ToF64
+            {
+                let mut mean = vec![0.; $col];
+                for row in self.0.chunks($col) {
+                    for (m, x) in mean.iter_mut().zip(row.iter()) {
+                        *m += $crate::ToF64::to_f64(*x);
+                    }
+                }
+                for m in mean.iter_mut() {
+                    *m /= $row as f64;
+                }
+                mean
+            }
+        }
+
+        impl ArrayMatrix for $st {
+            type Transpose = $tr;
+            type Element = $t;
+
+            fn row(&self) -> usize {
+                $row
+            }
+
+            fn column(&self) -> usize {
+                $col
+            }
+
+            fn size(&self) -> (usize, usize) {
+                (self.row(), self.column())
+            }
+
+            fn swap(&mut self, (a_i, a_j): (usize, usize), (b_i, b_j): (usize, usize)) {
+                self.0.swap(a_i * $col + a_j, b_i * $col + b_j);
+            }
+
+            fn trace(&self) -> $t {
+                let mut sum = <$t as $crate::Zero>::zero();
+                for i in 0..::std::cmp::min($row, $col) {
+                    sum += self[(i, i)];
+                }
+                sum
+            }
+
+            fn transpose(&self) -> $tr {
+                let mut trans = $tr([<$t as $crate::Zero>::zero(); $row * $col]);
+                assert_eq!(trans.size(), ($col, $row),
+                    "transposing a non-square matrix needs a `transpose:` companion type");
+                for i in 0..self.0.len() {
+                   let r = i / $col;
+                   let c = i % $col;
+                   trans[(c, r)] = self[(r, c)];
+                }
+                trans
+            }
+
+            fn transpose_mut(&mut self) {
+                assert!($row == $col, "transpose_mut requires a square matrix");
+                let mut positions = (0..self.0.len()).map(|i| {
+                   (i / $col, i % $col)
+                });
+                loop {
+                    if let Some((r, c)) = positions.next() {
+                        //println!("({}, {}) {}", r, c, self[(r, c)]);
+                        if r == c {
+                            if r < $row - 1 {
+                                // Consume the rest of the row to avoid double swapping
+                                let _ = positions.nth($row - r - 2);
+                            }
+                        } else {
+                            let a = r * $col + c;
+                            let b = c * $row + r;
+                            //assert_eq!(self[(r, c)], self.0[a]);
+                            //assert_eq!(self[(c, r)], self.0[b]);
+                            self.0.swap(a, b);
+                        }
+                    } else {
+                        break
+                    }
+                }
+            }
+        }
+
+        impl Index<(usize, usize)> for $st {
+            type Output = $t;
+
+            #[inline]
+            fn index(&self, (i, j): (usize, usize)) -> &$t {
+                assert!(i < $row && j < $col);
+                &self.0[i * $col + j]
+            }
+        }
+
+        impl IndexMut<(usize, usize)> for $st {
+
+            #[inline]
+            fn index_mut(&mut self, (i, j): (usize, usize)) -> &mut $t {
+                assert!(i < $row && j < $col);
+                &mut self.0[i * $col + j]
+            }
+        }
+
+        // Every cell is `$t::default()`.
+        impl Default for $st where for<'a> $t: Default {
+            fn default() -> Self {
+                $st::from_fn(|_| Default::default())
+            }
+        }
+
+        impl fmt::Debug for $st {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.debug_list().entries(self.0.iter()).finish()
+            }
+        }
+
+        // Prints one row per line with elements separated by spaces. The alternate
+        // flag (`{:#}`) adds a header with the dimensions.
+        impl fmt::Display for $st where for<'a> $t: fmt::Display {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                if f.alternate() {
+                    writeln!(f, "Matrix {}x{}:", $row, $col)?;
+                }
+                for i in 0..$row {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    for j in 0..$col {
+                        if j > 0 {
+                            write!(f, " ")?;
+                        }
+                        fmt::Display::fmt(&self[(i, j)], f)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        // Parses the grid written by `Display`, with or without the alternate header.
+        // Rows are separated by newlines and elements by any amount of whitespace.
+        impl ::std::str::FromStr for $st where for<'a> $t: ::std::str::FromStr {
+            type Err = $crate::ParseError;
+
+            fn from_str(s: &str) -> Result<$st, $crate::ParseError> {
+                let lines: Vec<&str> = s.lines()
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty())
+                    .collect();
+                let has_header = lines.first()
+                    .map(|line| line.starts_with("Matrix ") && line.ends_with(':'))
+                    .unwrap_or(false);
+                let rows = if has_header { &lines[1..] } else { &lines[..] };
+                if rows.len() != $row {
+                    return Err($crate::ParseError::RowCount { expected: $row, found: rows.len() });
+                }
+
+                let mut cells = Vec::with_capacity($row);
+                for (i, line) in rows.iter().enumerate() {
+                    let elements: Vec<&str> = line.split_whitespace().collect();
+                    if elements.len() != $col {
+                        return Err($crate::ParseError::RowLength {
+                            row: i,
+                            expected: $col,
+                            found: elements.len(),
+                        });
+                    }
+                    cells.push(elements);
+                }
+                $st::try_from_fn(|(i, j)| {
+                    cells[i][j].parse().map_err(|_| {
+                        $crate::ParseError::InvalidElement { row: i, column: j }
+                    })
+                })
+            }
+        }
+
+        impl Eq for $st {}
+
+        impl PartialEq for $st {
+            fn eq(&self, other: &$st) -> bool {
+                self.0[..] == other.0[..]
+            }
+        }
+
+        impl Add for $st {
+            type Output = $st;
+
+            fn add(self, other: $st) -> $st {
+                let mut a = self.0;
+                for (x, y) in a.iter_mut().zip(other.0.iter()) {
+                    *x += *y;
+                }
+                $st(a)
+            }
+        }
+
+        impl Add<$t> for $st {
+            type Output = $st;
+
+            fn add(self, other: $t) -> $st {
+                $st(self.0.map(|x| x + other))
+            }
+        }
+
+        impl AddAssign for $st {
+            fn add_assign(&mut self, other: $st) {
+                for i in 0..self.0.len() {
+                    self.0[i] += other.0[i];
+                }
+            }
+        }
+
+        impl AddAssign<$t> for $st {
+            fn add_assign(&mut self, other: $t) {
+                for i in 0..self.0.len() {
+                    self.0[i] += other;
+                }
+            }
+        }
+
+        impl Sub for $st {
+            type Output = $st;
+
+            fn sub(self, other: $st) -> $st {
+                let mut a = self.0;
+                for (x, y) in a.iter_mut().zip(other.0.iter()) {
+                    *x -= *y;
+                }
+                $st(a)
+            }
+        }
+
+        impl Sub<$t> for $st {
+            type Output = $st;
+
+            fn sub(self, other: $t) -> $st {
+                $st(self.0.map(|x| x - other))
+            }
+        }
+
+        impl SubAssign for $st {
+            fn sub_assign(&mut self, other: $st) {
+                for i in 0..self.0.len() {
+                    self.0[i] -= other.0[i];
+                }
+            }
+        }
+
+        impl SubAssign<$t> for $st {
+            fn sub_assign(&mut self, other: $t) {
+                for i in 0..self.0.len() {
+                    self.0[i] -= other;
+                }
+            }
+        }
+
+        impl Mul for $st {
+            type Output = $st;
+
+            fn mul(self, other: $st) -> $st {
+                assert_eq!(self.column(), other.row());
+
+                // Multiplying by the identity leaves the other operand unchanged,
+                // so skip the full product when either side is the identity. That only
+                // matches the full product (up to the sign of zero) when every element
+                // of the other operand gives `x * 0 == 0`. Infinities and NaN do not, so
+                // they take the full product and come out as NaN like any other product.
+                let zero = <$t as $crate::Zero>::zero();
+                let zero_absorbing = |m: &$st| m.0.iter().all(|x| *x * zero == zero);
+                if other.is_identity() && zero_absorbing(&self) {
+                    $crate::count_identity_fast_path();
+                    return self;
+                }
+                if self.is_identity() && zero_absorbing(&other) {
+                    $crate::count_identity_fast_path();
+                    return other;
+                }
+
+                // Unlike `inverse`, there is no debug invariant check here. The only check of a
+                // product is to compute it again the same way, and its rounding error is bounded
+                // by the sizes of the operands instead of being amplified as a solve's can be.
+                $st::from_product(&self, &other)
+            }
+        }
+
+        // Sparse-dense product that only visits the stored elements of the sparse matrix,
+        // which must have `$row` rows and columns.
+        impl Mul<$st> for $crate::SparseMatrix<$t> {
+            type Output = $st;
+
+            fn mul(self, other: $st) -> $st {
+                assert_eq!(self.size(), ($row, $row));
+                let mut result = $st([<$t as $crate::Zero>::zero(); $row * $col]);
+                for &((i, k), x) in self.entries() {
+                    for j in 0..$col {
+                        result[(i, j)] += x * other[(k, j)];
+                    }
+                }
+                result
+            }
+        }
+
+        impl Mul<$t> for $st {
+            type Output = $st;
+
+            fn mul(self, other: $t) -> $st {
+                let mut a = [<$t as $crate::Zero>::zero(); $row * $col];
+                for i in 0..a.len() {
+                    a[i] = self.0[i].clone() * other;
+                }
+                $st(a)
+            }
+        }
+
+        impl Mul<$st> for $t {
+            type Output = $st;
+
+            fn mul(self, other: $st) -> $st {
+                let mut a = [<$t as $crate::Zero>::zero(); $row * $col];
+                for i in 0..a.len() {
+                    a[i] = self * other.0[i];
+                }
+                $st(a)
+            }
+        }
+
+        impl MulAssign<$t> for $st {
+            fn mul_assign(&mut self, other: $t) {
+                for i in 0..self.0.len() {
+                    self.0[i] *= other;
+                }
+            }
+        }
+
+        impl Div<$t> for $st {
+            type Output = $st;
+
+            fn div(self, other: $t) -> $st {
+                $st(self.0.map(|x| x / other))
+            }
+        }
+
+        impl DivAssign<$t> for $st {
+            fn div_assign(&mut self, other: $t) {
+                for i in 0..self.0.len() {
+                    self.0[i] /= other;
+                }
+            }
+        }
+
+        impl ::std::ops::Neg for $st where for<'a> $t: ::std::ops::Neg<Output = $t> {
+            type Output = $st;
+
+            fn neg(self) -> $st {
+                let mut m = self;
+                for x in m.0.iter_mut() {
+                    *x = -*x;
+                }
+                m
+            }
+        }
+
+        impl ::std::iter::Sum for $st {
+            fn sum<I: Iterator<Item = $st>>(iter: I) -> $st {
+                iter.fold($st([<$t as $crate::Zero>::zero(); $row * $col]), |acc, m| acc + m)
+            }
+        }
+
+        impl<'a> ::std::iter::Sum<&'a $st> for $st {
+            fn sum<I: Iterator<Item = &'a $st>>(iter: I) -> $st {
+                let mut total = $st([<$t as $crate::Zero>::zero(); $row * $col]);
+                for m in iter {
+                    for i in 0..total.0.len() {
+                        total.0[i] += m.0[i];
+                    }
+                }
+                total
+            }
+        }
+
+        // Repeated matrix multiplication, so only meaningful for square matrices.
+        impl ::std::iter::Product for $st {
+            fn product<I: Iterator<Item = $st>>(iter: I) -> $st {
+                iter.fold($st::identity(), |acc, m| acc * m)
+            }
+        }
+    }
+}
+
+/// A macro that implements `Mul` between two matrix types generated by
+/// [`impl_matrix!`](macro.impl_matrix.html), producing a third matrix type with the
+/// resulting shape.
+///
+/// Example:
+///
+/// ```
+/// # #[macro_use] extern crate array_matrix;
+///
+/// # fn main() {
+/// use array_matrix::ArrayMatrix;
+/// use std::ops::{Index, IndexMut, Add, AddAssign, Sub, SubAssign, Mul, MulAssign, Div, DivAssign};
+/// use std::fmt;
+///
+/// impl_matrix!(Matrix2x3([i32; (2, 3)]));
+/// impl_matrix!(Matrix3x1([i32; (3, 1)]));
+/// impl_matrix!(Matrix2x1([i32; (2, 1)]));
+/// impl_matrix_mul!(Matrix2x3 * Matrix3x1 => Matrix2x1);
+///
+/// let a = Matrix2x3([1, 2, 3, 4, 5, 6]);
+/// let b = Matrix3x1([1, 0, -1]);
+///
+/// assert_eq!(a * b, Matrix2x1([-2, -2]));
+/// # }
+/// ```
+///
+#[macro_export]
+macro_rules! impl_matrix_mul {
+    ($lhs:ident * $rhs:ident => $out:ident) => {
+        impl ::std::ops::Mul<$rhs> for $lhs {
+            type Output = $out;
+
+            fn mul(self, other: $rhs) -> $out {
+                $out::from_product(&self, &other)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ArrayMatrix;
+    use std::ops::{Index, IndexMut, Add, AddAssign, Sub, SubAssign, Mul, MulAssign, Div, DivAssign};
+    use std::fmt;
+
+    #[test]
+    fn ident() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let m = TestMatrix::identity();
+
+        assert_eq!(m, TestMatrix::from_array([1, 0, 0, 1]));
+    }
+
+    #[test]
+    fn from_fn() {
+        impl_matrix!(TestMatrix([f32; (3, 4)]));
+        let m = TestMatrix::from_fn(|(i, j)| (i * 4 + j) as f32);
+        for (k, x) in m.0.iter().enumerate() {
+            assert_eq!(*x, k as f32);
+        }
+
+        let mut calls = Vec::new();
+        let _ = TestMatrix::from_fn(|pos| {
+            calls.push(pos);
+            0.
+        });
+        assert_eq!(calls[..3], [(0, 0), (0, 1), (0, 2)]);
+        assert_eq!(calls.len(), 12);
+    }
+
+    #[test]
+    fn try_from_fn() {
+        impl_matrix!(TestMatrix([i32; (2, 3)]));
+        let lookup = |(i, j): (usize, usize)| -> Result<i32, (usize, usize)> {
+            if (i, j) == (1, 1) { Err((i, j)) } else { Ok((i * 3 + j) as i32) }
+        };
+
+        assert_eq!(TestMatrix::try_from_fn(lookup), Err((1, 1)));
+        assert_eq!(TestMatrix::try_from_fn(|(i, j)| Ok::<i32, ()>((i * 3 + j) as i32)),
+                   Ok(TestMatrix([0, 1, 2, 3, 4, 5])));
+    }
+
+    #[test]
+    fn from_rows_vec() {
+        use ShapeError;
+
+        impl_matrix!(TestMatrix([i32; (2, 3)]));
+
+        assert_eq!(TestMatrix::from_rows_vec(vec![vec![1, 2, 3], vec![4, 5, 6]]),
+                   Ok(TestMatrix([1, 2, 3, 4, 5, 6])));
+        assert_eq!(TestMatrix::from_rows_vec(vec![vec![1, 2, 3], vec![4, 5]]),
+                   Err(ShapeError::RowLength { row: 1, expected: 3, found: 2 }));
+        assert_eq!(TestMatrix::from_rows_vec(vec![vec![1, 2, 3]]),
+                   Err(ShapeError::RowCount { expected: 2, found: 1 }));
+    }
+
+    #[test]
+    fn with_row_len() {
+        impl_matrix!(TestMatrix([i32; (3, 4)]));
+        impl_matrix!(TallMatrix([i32; (4, 3)]));
+        let m = TestMatrix([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+
+        let tall = TallMatrix::from_rows_vec(m.with_row_len(3).unwrap()).unwrap();
+        assert_eq!(tall[(1, 0)], 3);
+        assert_eq!(tall[(3, 2)], 11);
+        assert_eq!(m.with_row_len(6).unwrap(), vec![vec![0, 1, 2, 3, 4, 5], vec![6, 7, 8, 9, 10, 11]]);
+        assert_eq!(m.with_row_len(5), None);
+        assert_eq!(m.with_row_len(0), None);
+    }
+
+    #[test]
+    fn tridiagonal() {
+        impl_matrix!(TestMatrix([i32; (4, 4)]));
+        let m = TestMatrix::tridiagonal(-1, 2, 3);
+
+        assert_eq!(m, TestMatrix([
+            2, 3, 0, 0,
+            -1, 2, 3, 0,
+            0, -1, 2, 3,
+            0, 0, -1, 2,
+        ]));
+    }
+
+    #[test]
+    fn solve_tridiagonal() {
+        impl_matrix!(TestMatrix([f64; (3, 3)]));
+        let m = TestMatrix::tridiagonal(1., 4., 1.);
+        let x = m.solve_tridiagonal(&[6., 12., 14.]).unwrap();
+
+        for (actual, expected) in x.iter().zip([1., 2., 3.].iter()) {
+            assert!((actual - expected).abs() < 1e-12);
+        }
+
+        let full = TestMatrix([4., 1., 1., 1., 4., 1., 1., 1., 4.]);
+        assert_eq!(full.solve_tridiagonal(&[6., 12., 14.]), None);
+    }
+
+    #[test]
+    fn companion() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        // x^2 - 3x + 2 = (x - 1)(x - 2)
+        let m = TestMatrix::companion(&[2, -3]);
+
+        assert_eq!(m, TestMatrix([0, -2, 1, 3]));
+
+        impl_matrix!(CubicMatrix([i32; (3, 3)]));
+        let m = CubicMatrix::companion(&[4, 5, 6]);
+
+        assert_eq!(m, CubicMatrix([0, 0, -4, 1, 0, -5, 0, 1, -6]));
+    }
+
+    #[test]
     fn row_col() {
         const ROW: usize = 3;
         const COLUMN: usize = 3;
         impl_matrix!(TestMatrix([i32; (ROW, COLUMN)]));
         let m = TestMatrix([1, 2, 3, 4, 5, 6, 7, 8, 9]);
 
-        assert_eq!(m.row(), ROW);
-        assert_eq!(m.column(), COLUMN);
+        assert_eq!(m.row(), ROW);
+        assert_eq!(m.column(), COLUMN);
+    }
+
+    #[test]
+    fn trace() {
+        impl_matrix!(TestMatrix([i32; (3, 3)]));
+        let m = TestMatrix([1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(m.trace(), 15);
+
+        impl_matrix!(WideMatrix([i32; (2, 3)]));
+        assert_eq!(WideMatrix([1, 2, 3, 4, 5, 6]).trace(), 6);
+    }
+
+    #[test]
+    fn transpose() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let m = TestMatrix([1, 2, 3, 4]);
+        let trans = m.transpose();
+
+        assert_eq!(trans, TestMatrix([1, 3, 2, 4]));
+    }
+
+    #[test]
+    fn transpose_rectangular() {
+        impl_matrix!(WideMatrix([i32; (2, 3)]), transpose: TallMatrix);
+        impl_matrix!(TallMatrix([i32; (3, 2)]), transpose: WideMatrix);
+        let m = WideMatrix([
+            1, 2, 3,
+            4, 5, 6,
+        ]);
+        let trans = m.transpose();
+
+        assert_eq!(trans.size(), (3, 2));
+        assert_eq!(trans, TallMatrix([
+            1, 4,
+            2, 5,
+            3, 6,
+        ]));
+        assert_eq!(trans.transpose(), m);
+    }
+
+    #[test]
+    #[should_panic(expected = "companion type")]
+    fn transpose_rectangular_without_companion() {
+        impl_matrix!(TestMatrix([i32; (2, 3)]));
+        let _ = TestMatrix([1, 2, 3, 4, 5, 6]).transpose();
+    }
+
+    #[test]
+    fn transpose_mut() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let mut m = TestMatrix([1, 2, 3, 4]);
+        m.transpose_mut();
+
+        assert_eq!(m, TestMatrix([1, 3, 2, 4]));
+    }
+
+    #[test]
+    fn transpose_large() {
+        impl_matrix!(TestMatrix([i32; (6, 6)]));
+        let m = TestMatrix([
+                           0, 1, 2, 3, 4, 5,
+                           6, 7, 8, 9, 10, 11,
+                           12, 13, 14, 15, 16, 17,
+                           18, 19, 20, 21, 22, 23,
+                           24, 25, 26, 27, 28, 29,
+                           30, 31, 32, 33, 34, 35
+        ]);
+        let trans = m.transpose();
+
+        assert_eq!(trans,
+                   TestMatrix([
+                              0, 6, 12, 18, 24, 30,
+                              1, 7, 13, 19, 25, 31,
+                              2, 8, 14, 20, 26, 32,
+                              3, 9, 15, 21, 27, 33,
+                              4, 10, 16, 22, 28, 34,
+                              5, 11, 17, 23, 29, 35
+                   ]));
+    }
+
+    #[test]
+    fn transpose_recursive() {
+        impl_matrix!(TestMatrix([i32; (6, 6)]));
+        let mut m = TestMatrix([0; 36]);
+        for i in 0..36 {
+            m.0[i] = i as i32;
+        }
+        assert_eq!(TestMatrix(m.transpose_recursive()), m.transpose());
+
+        impl_matrix!(TallMatrix([i32; (40, 23)]));
+        let mut tall = TallMatrix([0; 920]);
+        for i in 0..920 {
+            tall.0[i] = i as i32;
+        }
+        let trans = tall.transpose_recursive();
+        for r in 0..40 {
+            for c in 0..23 {
+                assert_eq!(trans[c * 40 + r], tall[(r, c)]);
+            }
+        }
+    }
+
+    #[test]
+    fn insert_row() {
+        impl_matrix!(TestMatrix([i32; (3, 2)]));
+        let m = TestMatrix([1, 2, 3, 4, 5, 6]);
+        assert_eq!(m.insert_row(1, &[7, 8]), vec![vec![1, 2], vec![7, 8], vec![3, 4], vec![5, 6]]);
+        assert_eq!(m.insert_row(3, &[7, 8])[3], vec![7, 8]);
+        assert_eq!(m.insert_column(1, &[7, 8, 9]), vec![vec![1, 7, 2], vec![3, 8, 4], vec![5, 9, 6]]);
+    }
+
+    #[test]
+    #[should_panic(expected = "inserted row has 3 elements, expected 2")]
+    fn insert_row_wrong_length() {
+        impl_matrix!(TestMatrix([i32; (3, 2)]));
+        let _ = TestMatrix([1, 2, 3, 4, 5, 6]).insert_row(1, &[7, 8, 9]);
+    }
+
+    #[test]
+    fn remove_row() {
+        impl_matrix!(TestMatrix([i32; (3, 2)]));
+        let m = TestMatrix([1, 2, 3, 4, 5, 6]);
+        let (row, rest) = m.remove_row(1).unwrap();
+        assert_eq!(row, vec![3, 4]);
+        assert_eq!(rest, vec![vec![1, 2], vec![5, 6]]);
+
+        let (column, rest) = m.remove_column(0).unwrap();
+        assert_eq!(column, vec![1, 3, 5]);
+        assert_eq!(rest, vec![vec![2], vec![4], vec![6]]);
+
+        assert_eq!(m.remove_row(3), None);
+        assert_eq!(m.remove_column(2), None);
+    }
+
+    #[test]
+    fn index() {
+        impl_matrix!(TestMatrix([i32; (3, 3)]));
+        let m = TestMatrix([1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(m[(1, 2)], 6);
+    }
+
+    #[test]
+    fn index_mut() {
+        impl_matrix!(TestMatrix([i32; (3, 3)]));
+        let mut m = TestMatrix([1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(m[(1, 2)], 6);
+        m[(1, 2)] = 30;
+        assert_eq!(m[(1, 2)], 30);
+    }
+
+    #[test]
+    fn filled() {
+        impl_matrix!(TestMatrix([f32; (2, 3)]));
+        let m = TestMatrix::filled(2.5);
+        assert!(m.0.iter().all(|x| *x == 2.5));
+
+        assert!(TestMatrix::zeros().0.iter().all(|x| *x == 0.));
+    }
+
+    #[test]
+    fn at() {
+        use MatrixError;
+
+        impl_matrix!(TestMatrix([i32; (2, 3)]));
+        let m = TestMatrix([1, 2, 3, 4, 5, 6]);
+
+        assert_eq!(m.at(1, 2), Ok(&6));
+        assert_eq!(m.at(2, 0), Err(MatrixError::IndexOutOfBounds { i: 2, j: 0 }));
+        assert_eq!(m.at(0, 3), Err(MatrixError::IndexOutOfBounds { i: 0, j: 3 }));
+    }
+
+    #[test]
+    fn get() {
+        impl_matrix!(TestMatrix([i32; (2, 3)]));
+        let mut m = TestMatrix([1, 2, 3, 4, 5, 6]);
+
+        assert_eq!(m.get((1, 2)), Some(&6));
+        assert_eq!(m.get((2, 0)), None);
+        assert_eq!(m.get((0, 3)), None);
+
+        *m.get_mut((0, 1)).unwrap() = 20;
+        assert_eq!(m[(0, 1)], 20);
+        assert!(m.get_mut((0, 3)).is_none());
+        assert!(m.get_mut((5, 5)).is_none());
+    }
+
+    #[test]
+    fn as_slice() {
+        impl_matrix!(TestMatrix([i32; (2, 3)]));
+        let mut m = TestMatrix([1, 2, 3, 4, 5, 6]);
+
+        assert_eq!(m.as_slice().len(), m.row() * m.column());
+        assert_eq!(m.as_slice(), &[1, 2, 3, 4, 5, 6]);
+
+        m.as_mut_slice()[4] = 50;
+        assert_eq!(m[(1, 1)], 50);
+    }
+
+    #[test]
+    fn into_array() {
+        impl_matrix!(TestMatrix([i32; (2, 3)]));
+        let m = TestMatrix::from_array([1, 2, 3, 4, 5, 6]);
+
+        assert_eq!(m.into_array(), [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn display() {
+        impl_matrix!(TestMatrix([i32; (2, 3)]));
+        let m = TestMatrix([1, 2, 3, 4, 5, 6]);
+
+        assert_eq!(format!("{}", m), "1 2 3\n4 5 6");
+        assert_eq!(format!("{:#}", m), "Matrix 2x3:\n1 2 3\n4 5 6");
+    }
+
+    #[test]
+    fn from_str() {
+        use ParseError;
+
+        impl_matrix!(TestMatrix([f32; (2, 3)]));
+        let m = TestMatrix([1.5, -2., 3., 4.25, 5., 6.]);
+
+        assert_eq!(format!("{}", m).parse::<TestMatrix>(), Ok(TestMatrix([1.5, -2., 3., 4.25, 5., 6.])));
+        assert_eq!(format!("{:#}", m).parse::<TestMatrix>(), Ok(TestMatrix([1.5, -2., 3., 4.25, 5., 6.])));
+        assert_eq!("  1 2\t 3\n\n4   5 6  \n".parse::<TestMatrix>(), Ok(TestMatrix([1., 2., 3., 4., 5., 6.])));
+
+        assert_eq!("1 2 3\n4 5".parse::<TestMatrix>(),
+                   Err(ParseError::RowLength { row: 1, expected: 3, found: 2 }));
+        assert_eq!("1 2 3".parse::<TestMatrix>(),
+                   Err(ParseError::RowCount { expected: 2, found: 1 }));
+        assert_eq!("1 2 3\n4 x 6".parse::<TestMatrix>(),
+                   Err(ParseError::InvalidElement { row: 1, column: 1 }));
+    }
+
+    #[test]
+    fn eq() {
+        impl_matrix!(TestMatrix([i32; (3, 3)]));
+        let m_a = TestMatrix([1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let m_b = TestMatrix([1, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+        assert_eq!(m_a, m_b);
+    }
+
+    #[test]
+    fn add() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let m_a = TestMatrix([1, 2, 3, 4]);
+        let m_b = TestMatrix([1, 2, 3, 4]);
+        let m_c = m_a + m_b;
+
+        assert_eq!(m_c, TestMatrix([2, 4, 6, 8]));
+    }
+
+    #[test]
+    fn add_assign() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let mut m_a = TestMatrix([1, 2, 3, 4]);
+        let m_b = TestMatrix([1, 2, 3, 4]);
+        m_a += m_b;
+
+        assert_eq!(m_a, TestMatrix([2, 4, 6, 8]));
+    }
+
+    #[test]
+    fn add_scalar() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let m_a = TestMatrix([1, 2, 3, 4]);
+        let m_b = m_a + 1;
+
+        assert_eq!(m_b, TestMatrix([2, 3, 4, 5]));
+    }
+
+    #[test]
+    fn add_scalar_assign() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let mut m_a = TestMatrix([1, 2, 3, 4]);
+        m_a += 1;
+
+        assert_eq!(m_a, TestMatrix([2, 3, 4, 5]));
+    }
+
+    #[test]
+    fn subtract() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let m_a = TestMatrix([1, 2, 3, 4]);
+        let m_b = TestMatrix([1, 2, 3, 4]);
+        let m_c = m_a - m_b;
+
+        assert_eq!(m_c, TestMatrix([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn subtract_assign() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let mut m_a = TestMatrix([1, 2, 3, 4]);
+        let m_b = TestMatrix([1, 2, 3, 4]);
+        m_a -= m_b;
+
+        assert_eq!(m_a, TestMatrix([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn subtract_scalar() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let m_a = TestMatrix([1, 2, 3, 4]);
+        let m_b = m_a - 1;
+
+        assert_eq!(m_b, TestMatrix([0, 1, 2, 3]));
+    }
+
+    #[test]
+    fn subtract_scalar_assign() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let mut m_a = TestMatrix([1, 2, 3, 4]);
+        m_a -= 1;
+
+        assert_eq!(m_a, TestMatrix([0, 1, 2, 3]));
+    }
+
+    #[test]
+    fn multiply_identity() {
+        impl_matrix!(TestMatrix([f32; (2, 2)]));
+        let fast_paths = || ::IDENTITY_FAST_PATHS.with(|n| n.get());
+        let before = fast_paths();
+        let m = TestMatrix([1., 2., 3., 4.]);
+        assert_eq!(TestMatrix::identity() * m, TestMatrix([1., 2., 3., 4.]));
+        assert_eq!(m * TestMatrix::identity(), TestMatrix([1., 2., 3., 4.]));
+        assert_eq!(fast_paths(), before + 2);
+        assert_eq!(m * m, TestMatrix([7., 10., 15., 22.]));
+        assert_eq!(fast_paths(), before + 2);
+
+        // The fast path must not change the result: the full product turns an
+        // infinity into NaN through `0 * inf`, so multiplying by the identity does too.
+        let inf = f32::INFINITY;
+        let m_a = TestMatrix([inf, 2., 3., 4.]);
+        let left = TestMatrix::identity() * m_a;
+        assert_eq!(left[(0, 0)], inf);
+        assert!(left[(1, 0)].is_nan());
+        assert_eq!((left[(0, 1)], left[(1, 1)]), (2., 4.));
+        let right = m_a * TestMatrix::identity();
+        assert_eq!(right[(0, 0)], inf);
+        assert!(right[(0, 1)].is_nan());
+        assert_eq!((right[(1, 0)], right[(1, 1)]), (3., 4.));
+        assert_eq!(fast_paths(), before + 2);
+    }
+
+    #[test]
+    fn multiply_different_shapes() {
+        impl_matrix!(MatrixA([i32; (2, 3)]));
+        impl_matrix!(MatrixB([i32; (3, 4)]));
+        impl_matrix!(MatrixC([i32; (2, 4)]));
+        impl_matrix_mul!(MatrixA * MatrixB => MatrixC);
+        let m_a = MatrixA([1, 2, 3, 4, 5, 6]);
+        let m_b = MatrixB([
+            1, 0, 2, -1,
+            0, 1, 1, 2,
+            3, -1, 0, 1,
+        ]);
+        let m_c = m_a * m_b;
+
+        assert_eq!(m_c.size(), (2, 4));
+        assert_eq!(m_c, MatrixC([10, -1, 4, 6, 22, -1, 13, 12]));
+    }
+
+    #[test]
+    fn multiply() {
+        impl_matrix!(TestMatrix([i32; (3, 3)]));
+        let m_a = TestMatrix([1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let m_b = TestMatrix([1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let m_c = m_a * m_b;
+
+        assert_eq!(m_c, TestMatrix([30, 36, 42, 66, 81, 96, 102, 126, 150]));
+    }
+
+    #[test]
+    fn multiply_non_symmetric() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let m_ab = TestMatrix([1, 2, 3, 4]) * TestMatrix([0, 1, 5, -2]);
+        let m_ba = TestMatrix([0, 1, 5, -2]) * TestMatrix([1, 2, 3, 4]);
+
+        assert_eq!(m_ab, TestMatrix([10, -3, 20, -5]));
+        assert_eq!(m_ba, TestMatrix([3, 4, -1, 2]));
+    }
+
+    #[test]
+    fn copy_after_by_value_ops() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let a = TestMatrix([1, 2, 3, 4]);
+        let b = a;
+        let c = a + b;
+
+        assert_eq!(c, TestMatrix([2, 4, 6, 8]));
+        assert_eq!(a * b, TestMatrix([7, 10, 15, 22]));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn multiply_scalar() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let m_a = TestMatrix([1, 2, 3, 4]);
+        let m_b = m_a * 3;
+
+        assert_eq!(m_b, TestMatrix([3, 6, 9, 12]));
+    }
+
+    #[test]
+    fn multiply_scalar_left() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let m_a = TestMatrix([1, 2, 3, 4]);
+        let m_b = 3 * m_a;
+
+        assert_eq!(m_b, TestMatrix([3, 6, 9, 12]));
+        assert_eq!(m_b, m_a * 3);
+
+        impl_matrix!(FloatMatrix([f32; (2, 2)]));
+        assert_eq!(0.5 * FloatMatrix([2., 4., 6., 8.]), FloatMatrix([1., 2., 3., 4.]));
+    }
+
+    #[test]
+    fn negate() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let m = TestMatrix([1, -2, 3, -4]);
+
+        assert_eq!(-m, TestMatrix([-1, 2, -3, 4]));
+
+        // Unsigned element types still get every other impl.
+        impl_matrix!(UnsignedMatrix([u32; (2, 2)]));
+        assert_eq!(UnsignedMatrix([1, 2, 3, 4]) * 2, UnsignedMatrix([2, 4, 6, 8]));
+    }
+
+    #[test]
+    fn multiply_scalar_assign() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let mut m_a = TestMatrix([1, 2, 3, 4]);
+        m_a *= 3;
+
+        assert_eq!(m_a, TestMatrix([3, 6, 9, 12]));
+    }
+
+    #[test]
+    fn divide_scalar() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let m_a = TestMatrix([9, 12, 21, 36]);
+        let m_b = m_a / 3;
+
+        assert_eq!(m_b, TestMatrix([3, 4, 7, 12]));
+    }
+
+    #[test]
+    fn divide_scalar_assign() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let mut m_a = TestMatrix([9, 12, 21, 36]);
+        m_a /= 3;
+
+        assert_eq!(m_a, TestMatrix([3, 4, 7, 12]));
+    }
+
+    #[test]
+    fn sum() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let matrices = vec![
+            TestMatrix([1, 2, 3, 4]),
+            TestMatrix([5, 6, 7, 8]),
+            TestMatrix([-1, 0, 1, 2]),
+        ];
+        let total: TestMatrix = matrices.into_iter().sum();
+
+        assert_eq!(total, TestMatrix([5, 8, 11, 14]));
+    }
+
+    #[test]
+    fn to_sparse() {
+        impl_matrix!(TestMatrix([i32; (5, 5)]));
+        let m = TestMatrix([
+            0, 0, 3, 0, 0,
+            0, 0, 0, 0, 0,
+            1, 0, 0, 0, -2,
+            0, 0, 0, 0, 0,
+            0, 4, 0, 0, 0,
+        ]);
+        let sparse = m.to_sparse();
+
+        assert_eq!(sparse.nnz(), 4);
+        assert_eq!(sparse.get((2, 4)), Some(&-2));
+        assert_eq!(sparse.get((1, 1)), None);
+        assert_eq!(TestMatrix::from_sparse(&sparse), m);
+    }
+
+    #[test]
+    fn multiply_sparse() {
+        impl_matrix!(TestMatrix([i32; (4, 4)]));
+        let laplacian = TestMatrix([
+            1, -1, 0, 0,
+            -1, 2, -1, 0,
+            0, -1, 2, -1,
+            0, 0, -1, 1,
+        ]);
+        let sparse = laplacian.to_sparse();
+        let dense = TestMatrix([3, 1, 4, 1, 5, 9, 2, 6, 5, 3, 5, 8, 9, 7, 9, 3]);
+
+        let expected = laplacian * TestMatrix([3, 1, 4, 1, 5, 9, 2, 6, 5, 3, 5, 8, 9, 7, 9, 3]);
+
+        assert_eq!(sparse * dense, expected);
+    }
+
+    #[test]
+    fn is_zero() {
+        impl_matrix!(TestMatrix([f64; (2, 2)]));
+        impl_matrix!(IntMatrix([i32; (2, 2)]));
+
+        assert!(TestMatrix([0.; 4]).is_zero(0.));
+        assert!(TestMatrix([0., 1e-9, 0., 0.]).is_zero(1e-6));
+        assert!(!TestMatrix([0., 1e-3, 0., 0.]).is_zero(1e-6));
+        assert!(IntMatrix([0; 4]).is_zero_exact());
+        assert!(!IntMatrix([0, 0, 1, 0]).is_zero_exact());
+    }
+
+    #[test]
+    fn average() {
+        impl_matrix!(TestMatrix([f64; (2, 2)]));
+        let matrices = [
+            TestMatrix([1., 2., 3., 4.]),
+            TestMatrix([3., 2., 1., 0.]),
+            TestMatrix([2., 5., 8., -1.]),
+        ];
+
+        assert_eq!(TestMatrix::average(&matrices), Some(TestMatrix([2., 3., 4., 1.])));
+        assert_eq!(TestMatrix::average(&[]), None);
+    }
+
+    #[test]
+    fn weighted_sum() {
+        impl_matrix!(TestMatrix([f64; (2, 2)]));
+        let m_a = TestMatrix([1., 2., 3., 4.]);
+        let m_b = TestMatrix([10., 0., -10., 1.]);
+        let m_c = TestMatrix::weighted_sum(&[(0.3, &m_a), (0.7, &m_b)]);
+
+        let expected = [7.3, 0.6, -6.1, 1.9];
+        for (actual, expected) in m_c.0.iter().zip(expected.iter()) {
+            assert!((actual - expected).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn lerp() {
+        impl_matrix!(TestMatrix([f32; (2, 2)]));
+        let m_a = TestMatrix([0., 2., -4., 1.]);
+        let m_b = TestMatrix([4., 2., 4., 2.]);
+
+        assert_eq!(m_a.lerp(&m_b, 0.5), TestMatrix([2., 2., 0., 1.5]));
+        assert_eq!(m_a.lerp(&m_b, 0.), m_a);
+        assert_eq!(m_a.lerp(&m_b, 1.), m_b);
+        assert_eq!(m_a.lerp(&m_b, 2.), TestMatrix([8., 2., 12., 3.]));
+    }
+
+    #[test]
+    fn row_stochastic() {
+        impl_matrix!(TestMatrix([f32; (3, 3)]));
+        let valid = TestMatrix([
+            0.5, 0.25, 0.25,
+            0.1, 0.8, 0.1,
+            0., 0., 1.,
+        ]);
+        let negative = TestMatrix([
+            1.5, -0.5, 0.,
+            0.1, 0.8, 0.1,
+            0., 0., 1.,
+        ]);
+        let short_row = TestMatrix([
+            0.5, 0.25, 0.25,
+            0.1, 0.7, 0.1,
+            0., 0., 1.,
+        ]);
+
+        assert!(valid.is_row_stochastic(1e-6));
+        assert!(!negative.is_row_stochastic(1e-6));
+        assert!(!short_row.is_row_stochastic(1e-6));
+    }
+
+    #[test]
+    fn block() {
+        impl_matrix!(TestMatrix([i32; (4, 4)]));
+        impl_matrix!(BlockMatrix([i32; (2, 2)]));
+        let m = TestMatrix([
+            1, 2, 3, 4,
+            5, 6, 7, 8,
+            9, 10, 11, 12,
+            13, 14, 15, 16,
+        ]);
+
+        assert_eq!(m.block(1, 1, 2, 2), vec![vec![6, 7], vec![10, 11]]);
+        assert_eq!(BlockMatrix::from_rows_vec(m.block(1, 1, 2, 2)), Ok(BlockMatrix([6, 7, 10, 11])));
+        assert_eq!(m.block(3, 0, 1, 4), vec![vec![13, 14, 15, 16]]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn block_out_of_bounds() {
+        impl_matrix!(TestMatrix([i32; (4, 4)]));
+        let _ = TestMatrix([0; 16]).block(3, 3, 2, 2);
+    }
+
+    #[test]
+    fn set_block() {
+        use ShapeError;
+
+        impl_matrix!(TestMatrix([i32; (4, 4)]));
+        impl_matrix!(BlockMatrix([i32; (2, 2)]));
+        let mut m = TestMatrix([9; 16]);
+        let src = BlockMatrix([1, 2, 3, 4]);
+
+        assert_eq!(m.set_block(2, 2, &src), Ok(()));
+        assert_eq!(m, TestMatrix([
+            9, 9, 9, 9,
+            9, 9, 9, 9,
+            9, 9, 1, 2,
+            9, 9, 3, 4,
+        ]));
+        assert_eq!(m.set_block(3, 0, &src),
+                   Err(ShapeError::BlockOutOfBounds { at: (3, 0), size: (2, 2) }));
+    }
+
+    #[test]
+    fn swap_rows_tracked() {
+        impl_matrix!(TestMatrix([i32; (3, 2)]));
+        let mut m = TestMatrix([1, 2, 3, 4, 5, 6]);
+        let mut perm = [0, 1, 2];
+        m.swap_rows_tracked(0, 2, &mut perm);
+
+        assert_eq!(m, TestMatrix([5, 6, 3, 4, 1, 2]));
+        assert_eq!(perm, [2, 1, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "rows 0 and 3 out of bounds for a 3x2 matrix")]
+    fn swap_rows_tracked_out_of_bounds() {
+        impl_matrix!(TestMatrix([i32; (3, 2)]));
+        let mut m = TestMatrix([1, 2, 3, 4, 5, 6]);
+        m.swap_rows_tracked(0, 3, &mut [0, 1, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "permutation of length 4 does not match a 3x2 matrix")]
+    fn swap_rows_tracked_long_permutation() {
+        impl_matrix!(TestMatrix([i32; (3, 2)]));
+        let mut m = TestMatrix([1, 2, 3, 4, 5, 6]);
+        m.swap_rows_tracked(0, 2, &mut [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn approx_eq() {
+        impl_matrix!(TestMatrix([f64; (2, 2)]));
+        let a = TestMatrix([4., 7., 2., 6.]);
+        let round_trip = a * a.inverse().unwrap();
+        assert!(round_trip.approx_eq(&TestMatrix::identity(), 1e-12));
+
+        let b = TestMatrix([4. + 1e-9, 7., 2., 6. - 1e-9]);
+        assert!(a.approx_eq(&b, 1e-8));
+        assert!(b.approx_eq(&a, 1e-8));
+        assert!(!a.approx_eq(&b, 1e-10));
+        assert!(!a.approx_eq(&TestMatrix([4., 7., 2., f64::NAN]), 1.));
+    }
+
+    #[test]
+    fn approx_eq_per_column() {
+        impl_matrix!(TestMatrix([f64; (2, 2)]));
+        let m_a = TestMatrix([1000., 0.001, 2000., 0.002]);
+        let m_b = TestMatrix([1000.5, 0.001, 1999.8, 0.0025]);
+
+        assert!(m_a.approx_eq_per_column(&m_b, &[1., 0.001]));
+        assert!(!m_a.approx_eq_per_column(&m_b, &[1., 0.0001]));
+        assert!(!m_a.approx_eq_per_column(&m_b, &[0.1, 0.001]));
+    }
+
+    #[test]
+    fn diff() {
+        impl_matrix!(TestMatrix([i32; (6, 6)]));
+        let m_a = TestMatrix([1; 36]);
+        let mut m_b = TestMatrix([1; 36]);
+        m_b[(2, 5)] = 7;
+        m_b[(4, 0)] = -1;
+
+        assert_eq!(m_a.diff(&m_b), vec![((2, 5), 1, 7), ((4, 0), 1, -1)]);
+        assert_eq!(m_a.diff(&m_a), vec![]);
+    }
+
+    #[test]
+    fn map_indexed() {
+        impl_matrix!(TestMatrix([i32; (3, 3)]));
+        let m = TestMatrix([1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let upper = m.map_indexed(|(i, j), x| if i > j { 0 } else { x });
+
+        assert_eq!(upper, TestMatrix([1, 2, 3, 0, 5, 6, 0, 0, 9]));
+    }
+
+    #[test]
+    fn map() {
+        impl_matrix!(TestMatrix([i32; (2, 3)]));
+        let m = TestMatrix([1, -2, 3, -4, 5, -6]);
+        assert_eq!(m.map(|x| x * x), TestMatrix([1, 4, 9, 16, 25, 36]));
+
+        let mut seen = Vec::new();
+        let _ = m.map(|&x| {
+            seen.push(x);
+            x
+        });
+        assert_eq!(seen, vec![1, -2, 3, -4, 5, -6]);
+    }
+
+    #[test]
+    fn triangular() {
+        impl_matrix!(TestMatrix([i32; (3, 3)]));
+        let m = TestMatrix([1, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+        assert_eq!(m.upper_triangular(true), TestMatrix([1, 2, 3, 0, 5, 6, 0, 0, 9]));
+        assert_eq!(m.upper_triangular(false), TestMatrix([0, 2, 3, 0, 0, 6, 0, 0, 0]));
+        assert_eq!(m.lower_triangular(true), TestMatrix([1, 0, 0, 4, 5, 0, 7, 8, 9]));
+        assert_eq!(m.lower_triangular(false), TestMatrix([0, 0, 0, 4, 0, 0, 7, 8, 0]));
+    }
+
+    #[test]
+    fn div_round() {
+        use RoundMode;
+
+        impl_matrix!(TestMatrix([i32; (2, 3)]));
+        let m = TestMatrix([7, -7, 8, -8, 6, 4]);
+
+        assert_eq!(m.div_round(3, RoundMode::Trunc), TestMatrix([2, -2, 2, -2, 2, 1]));
+        assert_eq!(m.div_round(3, RoundMode::Floor), TestMatrix([2, -3, 2, -3, 2, 1]));
+        assert_eq!(m.div_round(3, RoundMode::Ceil), TestMatrix([3, -2, 3, -2, 2, 2]));
+        assert_eq!(m.div_round(3, RoundMode::Nearest), TestMatrix([2, -2, 3, -3, 2, 1]));
+        assert_eq!(m.div_round(-3, RoundMode::Floor), TestMatrix([-3, 2, -3, 2, -2, -2]));
+        assert_eq!(m.div_round(2, RoundMode::Nearest), TestMatrix([4, -4, 4, -4, 3, 2]));
+    }
+
+    #[test]
+    fn reduce() {
+        impl_matrix!(TestMatrix([i32; (2, 3)]));
+        let m = TestMatrix([3, -1, 4, 1, -5, 9]);
+
+        assert_eq!(m.reduce(1, |a, b| a * b), 540);
+        assert_eq!(m.reduce(i32::MIN, |a, b| a.max(b)), 9);
+    }
+
+    #[test]
+    fn frobenius_inner() {
+        impl_matrix!(TestMatrix([i32; (2, 3)]));
+        let m_a = TestMatrix([1, 2, 3, 4, 5, 6]);
+        let m_b = TestMatrix([-1, 0, 2, 3, 1, -2]);
+
+        let mut expected = 0;
+        for i in 0..2 {
+            for j in 0..3 {
+                expected += m_a[(i, j)] * m_b[(i, j)];
+            }
+        }
+        assert_eq!(m_a.frobenius_inner(&m_b), expected);
+        assert_eq!(m_a.frobenius_inner(&m_b), 10);
+    }
+
+    #[test]
+    fn rank1_update() {
+        impl_matrix!(TestMatrix([i32; (2, 3)]));
+        let mut m = TestMatrix([1, 2, 3, 4, 5, 6]);
+        let u = [1, -2];
+        let v = [3, 0, 1];
+        m.rank1_update(2, &u, &v);
+
+        let mut outer = TestMatrix([0; 6]);
+        for i in 0..2 {
+            for j in 0..3 {
+                outer[(i, j)] = u[i] * v[j];
+            }
+        }
+        assert_eq!(m, TestMatrix([1, 2, 3, 4, 5, 6]) + outer * 2);
+    }
+
+    #[test]
+    fn flop_count() {
+        impl_matrix!(TestMatrix([i32; (3, 3)]));
+        let m = TestMatrix([0; 9]);
+
+        assert_eq!(m.flop_count(&m), 27);
+    }
+
+    #[test]
+    fn transform_columns() {
+        impl_matrix!(TestMatrix([i32; (3, 3)]));
+        let rotate = TestMatrix([0, -1, 0, 1, 0, 0, 0, 0, 1]);
+        let points = TestMatrix([1, 0, 2, 0, 1, 3, 5, 5, 1]);
+        let transformed = rotate.transform_columns(&points);
+
+        assert_eq!(transformed, TestMatrix([0, -1, -3, 1, 0, 2, 5, 5, 1]));
+        assert_eq!(transformed,
+                   TestMatrix([0, -1, 0, 1, 0, 0, 0, 0, 1]) * TestMatrix([1, 0, 2, 0, 1, 3, 5, 5, 1]));
+    }
+
+    #[test]
+    fn similarity_transform() {
+        impl_matrix!(TestMatrix([f32; (2, 2)]));
+        // m = p * diag(2, 3) * p^-1
+        let m = TestMatrix([2., 1., 0., 3.]);
+        let p = TestMatrix([1., 1., 0., 1.]);
+        let d = m.similarity_transform(&p).unwrap();
+
+        for (actual, expected) in d.0.iter().zip([2., 0., 0., 3.].iter()) {
+            assert!((actual - expected).abs() < 1e-6);
+        }
+        assert_eq!(m.similarity_transform(&TestMatrix([1., 2., 2., 4.])), None);
+    }
+
+    #[test]
+    fn round_to_int() {
+        impl_matrix!(TestMatrix([f64; (2, 3)]));
+        impl_matrix!(IntMatrix([i64; (2, 3)]));
+        let m = TestMatrix([2.9999999, -1.0000001, 0.4, 2.5, -2.5, 17.00002]);
+
+        assert_eq!(IntMatrix::from_array(m.round_to_int()), IntMatrix([3, -1, 0, 3, -3, 17]));
+    }
+
+    #[test]
+    fn similarity_transform_ill_conditioned() {
+        impl_matrix!(TestMatrix([f32; (2, 2)]));
+        let m = TestMatrix([0.1, 0.7, 0.3, 0.9]);
+        // Nearly singular, so the f32 result is coarse, but only as coarse as the condition of
+        // `p` allows. The debug check accepts it.
+        let p = TestMatrix([1., 1., 1., 1.000001]);
+        assert!(m.similarity_transform(&p).is_some());
+    }
+
+    #[test]
+    fn determinant_f64() {
+        impl_matrix!(TestMatrix([i32; (5, 5)]));
+        // Rows of L * U with diag(U) = (100, 200, 300, 400, 500), then two rows swapped.
+        let m = TestMatrix([
+            -100, 593, 315, -15, 4,
+            200, 214, -2, 4, 4,
+            100, 7, -3, 5, 1,
+            0, 200, -596, 378, 7,
+            100, 7, 297, 813, 506,
+        ]);
+        let expected = -1.2e12;
+
+        assert!(expected < i32::MIN as f64);
+        assert!((m.determinant_f64() - expected).abs() < 1e-9 * expected.abs());
+        assert_eq!(TestMatrix([1; 25]).determinant_f64(), 0.);
+    }
+
+    #[test]
+    fn determinant_verbose() {
+        impl_matrix!(TestMatrix([i32; (3, 3)]));
+        // The zero in the top left forces a swap with the largest row below it.
+        let m = TestMatrix([
+            0, 2, 1,
+            4, 1, 0,
+            0, 0, 3,
+        ]);
+        let (det, swaps) = m.determinant_verbose().unwrap();
+        assert_eq!(swaps, 1);
+        assert!((det + 24.).abs() < 1e-12);
+
+        assert_eq!(TestMatrix::identity().determinant_verbose(), Some((1., 0)));
+        assert_eq!(TestMatrix([1; 9]).determinant_verbose(), None);
+    }
+
+    #[test]
+    fn solve_with_pivoting() {
+        use Pivoting;
+        impl_matrix!(TestMatrix([f64; (2, 2)]));
+        impl_matrix!(SingularMatrix([i32; (3, 3)]));
+        // The exact solution is within 1e-14 of (1, 1).
+        let m = TestMatrix([1e-15, 1., 1., 1.]);
+        let b = [1., 2.];
+
+        let partial = m.solve_with_pivoting(&b, Pivoting::Partial).unwrap();
+        let full = m.solve_with_pivoting(&b, Pivoting::Full).unwrap();
+        for x in partial.iter().chain(full.iter()) {
+            assert!((x - 1.).abs() < 1e-12);
+        }
+        // Without pivoting the tiny pivot magnifies rounding errors in the first unknown.
+        let none = m.solve_with_pivoting(&b, Pivoting::None).unwrap();
+        assert!((none[0] - 1.).abs() > 1e-4);
+        assert!((none[1] - 1.).abs() < 1e-12);
+        // A pivot within the zero tolerance cannot be divided by at all.
+        let tiny = TestMatrix([1e-20, 1., 1., 1.]);
+        assert_eq!(tiny.solve_with_pivoting(&b, Pivoting::None), None);
+        assert!(tiny.solve_with_pivoting(&b, Pivoting::Partial).is_some());
+
+        let singular = SingularMatrix([1, 2, 3, 2, 4, 6, 1, 0, 1]);
+        assert_eq!(singular.solve_with_pivoting(&[1., 2., 3.], Pivoting::Partial), None);
+        assert_eq!(singular.solve_with_pivoting(&[1., 2., 3.], Pivoting::Full), None);
+    }
+
+    #[test]
+    fn custom_element_identity() {
+        use {One, Zero};
+
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        struct Mod5(u8);
+
+        impl Zero for Mod5 {
+            fn zero() -> Self {
+                Mod5(0)
+            }
+        }
+
+        impl One for Mod5 {
+            fn one() -> Self {
+                Mod5(1)
+            }
+        }
+
+        impl Add for Mod5 {
+            type Output = Mod5;
+            fn add(self, other: Mod5) -> Mod5 {
+                Mod5((self.0 + other.0) % 5)
+            }
+        }
+
+        impl Sub for Mod5 {
+            type Output = Mod5;
+            fn sub(self, other: Mod5) -> Mod5 {
+                Mod5((self.0 + 5 - other.0) % 5)
+            }
+        }
+
+        impl Mul for Mod5 {
+            type Output = Mod5;
+            fn mul(self, other: Mod5) -> Mod5 {
+                Mod5(self.0 * other.0 % 5)
+            }
+        }
+
+        impl Div for Mod5 {
+            type Output = Mod5;
+            fn div(self, other: Mod5) -> Mod5 {
+                // x^3 is the inverse of x modulo 5
+                self * other * other * other
+            }
+        }
+
+        impl AddAssign for Mod5 {
+            fn add_assign(&mut self, other: Mod5) {
+                *self = *self + other;
+            }
+        }
+
+        impl SubAssign for Mod5 {
+            fn sub_assign(&mut self, other: Mod5) {
+                *self = *self - other;
+            }
+        }
+
+        impl MulAssign for Mod5 {
+            fn mul_assign(&mut self, other: Mod5) {
+                *self = *self * other;
+            }
+        }
+
+        impl DivAssign for Mod5 {
+            fn div_assign(&mut self, other: Mod5) {
+                *self = *self / other;
+            }
+        }
+
+        impl_matrix!(TestMatrix([Mod5; (2, 2)]));
+        let m = TestMatrix::identity();
+        assert_eq!(m.0, [Mod5(1), Mod5(0), Mod5(0), Mod5(1)]);
+        assert!(m.is_identity());
+
+        let a = TestMatrix([Mod5(2), Mod5(3), Mod5(4), Mod5(1)]);
+        assert_eq!((m * a).0, [Mod5(2), Mod5(3), Mod5(4), Mod5(1)]);
+    }
+
+    #[test]
+    fn nearest_orthogonal() {
+        impl_matrix!(TestMatrix([f64; (3, 3)]));
+        let (sin, cos) = (0.5f64, 0.75f64.sqrt());
+        let drifted = TestMatrix([
+            cos + 0.01, -sin, 0.02,
+            sin - 0.01, cos + 0.015, 0.,
+            0., 0.01, 0.99,
+        ]);
+        let q = drifted.nearest_orthogonal();
+
+        for i in 0..3 {
+            for j in 0..3 {
+                let mut dot = 0.;
+                for k in 0..3 {
+                    dot += q[(k, i)] * q[(k, j)];
+                }
+                let expected = if i == j { 1. } else { 0. };
+                assert!((dot - expected).abs() < 1e-9);
+            }
+        }
+        let rotation = [cos, -sin, 0., sin, cos, 0., 0., 0., 1.];
+        for (actual, expected) in q.0.iter().zip(rotation.iter()) {
+            assert!((actual - expected).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn permanent() {
+        impl_matrix!(TestMatrix([i32; (3, 3)]));
+        let m = TestMatrix([1, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+        // 1 * (5*9 + 6*8) + 2 * (4*9 + 6*7) + 3 * (4*8 + 5*7)
+        assert_eq!(m.permanent(), 450);
+        assert_eq!(TestMatrix::identity().permanent(), 1);
+    }
+
+    #[test]
+    fn product() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let matrices = vec![
+            TestMatrix([1, 1, 0, 1]),
+            TestMatrix([1, 1, 0, 1]),
+            TestMatrix([2, 0, 0, 2]),
+        ];
+        let total: TestMatrix = matrices.into_iter().product();
+
+        assert_eq!(total, TestMatrix([2, 4, 0, 2]));
+    }
+
+    #[test]
+    fn impl_two_matrix() {
+        impl_matrix!(TestMatrixA([i32; (2, 2)]));
+        impl_matrix!(TestMatrixB([f32; (2, 2)]));
+
+        let m_a = TestMatrixA([1, 2, 3, 4]);
+        let m_b = TestMatrixB([1., 2., 3., 4.]);
+
+        assert_eq!(m_a[(0, 0)] as f32, m_b[(0, 0)]);
+    }
+
+    #[test]
+    fn large_matrix() {
+        impl_matrix!(TestMatrixA([i32; (6, 6)]));
+        let m_a = TestMatrixA([1; 36]);
+        let m_b = TestMatrixA([1; 36]);
+
+        assert_eq!(m_a, m_b);
+    }
+
+    #[test]
+    fn rows() {
+        impl_matrix!(TestMatrix([i32; (3, 2)]));
+        let m = TestMatrix([1, 2, 3, 4, 5, 6]);
+        assert_eq!(m.rows().len(), 3);
+        for row in m.rows() {
+            assert_eq!(row.len(), 2);
+        }
+        assert_eq!(m.rows().collect::<Vec<_>>(), vec![&[1, 2][..], &[3, 4], &[5, 6]]);
+    }
+
+    #[test]
+    fn iter_rows_mut() {
+        impl_matrix!(TestMatrix([i32; (3, 2)]));
+        let mut m = TestMatrix([1, 2, 3, 4, 5, 6]);
+        for (row, factor) in m.iter_rows_mut().zip(&[1, 10, -1]) {
+            assert_eq!(row.len(), 2);
+            for x in row.iter_mut() {
+                *x *= *factor;
+            }
+        }
+
+        assert_eq!(m, TestMatrix([1, 2, 30, 40, -5, -6]));
+    }
+
+    #[test]
+    fn spectral_radius() {
+        impl_matrix!(TestMatrix([f64; (2, 2)]));
+        // Eigenvalues 3 and 1.
+        let m = TestMatrix([2., 1., 1., 2.]);
+        assert!((m.spectral_radius(50) - 3.).abs() < 1e-9);
+
+        // Eigenvalues -0.5 and 0.25, so an iteration with this matrix converges.
+        let m = TestMatrix([-0.5, 0., 0., 0.25]);
+        let radius = m.spectral_radius(50);
+        assert!((radius - 0.5).abs() < 1e-9);
+        assert!(radius < 1.);
+    }
+
+    #[test]
+    fn permutation() {
+        impl_matrix!(TestMatrix([i32; (3, 3)]));
+        let p = TestMatrix::permutation(&[2, 0, 1]);
+        assert_eq!(p, TestMatrix([0, 0, 1, 1, 0, 0, 0, 1, 0]));
+
+        let m = TestMatrix([
+            1, 2, 3,
+            4, 5, 6,
+            7, 8, 9,
+        ]);
+        assert_eq!(p * m, TestMatrix([
+            7, 8, 9,
+            1, 2, 3,
+            4, 5, 6,
+        ]));
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a permutation")]
+    fn permutation_repeated_index() {
+        impl_matrix!(TestMatrix([i32; (3, 3)]));
+        let _ = TestMatrix::permutation(&[0, 2, 0]);
+    }
+
+    #[test]
+    fn vandermonde() {
+        impl_matrix!(TestMatrix([i32; (3, 3)]));
+        let m = TestMatrix::vandermonde(&[1, 2, 3], 2);
+
+        assert_eq!(m, TestMatrix([
+            1, 1, 1,
+            1, 2, 4,
+            1, 3, 9,
+        ]));
+    }
+
+    #[test]
+    fn dft() {
+        impl_matrix!(TestMatrix([f64; (4, 4)]));
+        let (re, im) = TestMatrix::dft();
+        // exp(-2πi / 4) = -i
+        assert!(re[(1, 1)].abs() < 1e-12 && (im[(1, 1)] + 1.).abs() < 1e-12);
+
+        // A constant signal has all of its energy at frequency zero.
+        for j in 0..4 {
+            let x: f64 = (0..4).map(|k| re[(j, k)]).sum();
+            let y: f64 = (0..4).map(|k| im[(j, k)]).sum();
+            let expected = if j == 0 { 4. } else { 0. };
+            assert!((x - expected).abs() < 1e-12);
+            assert!(y.abs() < 1e-12);
+        }
     }
 
     #[test]
-    fn transpose() {
+    fn undo_tracked_edits() {
         impl_matrix!(TestMatrix([i32; (2, 2)]));
-        let m = TestMatrix([1, 2, 3, 4]);
-        let trans = m.transpose();
+        let mut m = TestMatrix([1, 2, 3, 4]);
+        let mut log = Vec::new();
+        m.set_tracked((0, 0), 10, &mut log);
+        m.set_tracked((1, 1), 40, &mut log);
+        m.set_tracked((0, 0), 100, &mut log);
+        assert_eq!(m, TestMatrix([100, 2, 3, 40]));
+        assert_eq!(log.len(), 3);
 
-        assert_eq!(trans, TestMatrix([1, 3, 2, 4]));
+        m.undo(&mut log);
+        assert_eq!(m, TestMatrix([1, 2, 3, 4]));
+        assert!(log.is_empty());
+
+        m.set((1, 0), 30);
+        assert_eq!(m, TestMatrix([1, 2, 30, 4]));
     }
 
     #[test]
-    fn transpose_mut() {
+    fn commutes_with() {
+        impl_matrix!(TestMatrix([f64; (2, 2)]));
+        let a = TestMatrix([2., 0., 0., 3.]);
+        let b = TestMatrix([0.1, 0., 0., -7.]);
+        assert!(a.commutes_with(&b, 1e-12));
+        assert!(b.commutes_with(&a, 1e-12));
+
+        let c = TestMatrix([0., 1., 0., 0.]);
+        assert!(!a.commutes_with(&c, 1e-12));
+        assert!(c.commutes_with(&c, 0.));
+    }
+
+    #[test]
+    fn commutator() {
         impl_matrix!(TestMatrix([i32; (2, 2)]));
-        let mut m = TestMatrix([1, 2, 3, 4]);
-        m.transpose_mut();
+        let a = TestMatrix([1, 2, 3, 4]);
+        let b = TestMatrix([3, 4, 6, 9]);
+        assert!(a.commutator(&b).is_zero_exact());
+        assert!(a.commutator(&a).is_zero_exact());
 
-        assert_eq!(m, TestMatrix([1, 3, 2, 4]));
+        let x = TestMatrix([0, 1, 0, 0]);
+        let y = TestMatrix([0, 0, 1, 0]);
+        assert_eq!(x.commutator(&y), TestMatrix([1, 0, 0, -1]));
     }
 
     #[test]
-    fn transpose_large() {
-        impl_matrix!(TestMatrix([i32; (6, 6)]));
+    fn to_joint_distribution() {
+        impl_matrix!(TestMatrix([f64; (2, 3)]));
+        let p = TestMatrix([1., 3., 0., 2., 2., 2.]).to_joint_distribution();
+        assert!((p.0.iter().sum::<f64>() - 1.).abs() < 1e-12);
+        assert!((p[(0, 1)] - 0.3).abs() < 1e-12);
+
+        let uniform = TestMatrix([0.; 6]).to_joint_distribution();
+        for x in uniform.0.iter() {
+            assert!((x - 1. / 6.).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn entropy() {
+        impl_matrix!(TestMatrix([f64; (2, 4)]));
+        let uniform = TestMatrix([0.125; 8]);
+        assert!((uniform.entropy() - 8f64.log2()).abs() < 1e-12);
+
+        let skewed = TestMatrix([0.5, 0., 0., 0.25, 0., 0., 0.25, 0.]);
+        assert!((skewed.entropy() - 1.5).abs() < 1e-12);
+        assert_eq!(TestMatrix([0., 0., 0., 1., 0., 0., 0., 0.]).entropy(), 0.);
+    }
+
+    #[test]
+    fn laplacian() {
+        impl_matrix!(TestMatrix([i32; (4, 4)]));
+        // A path 0 - 1 - 2 plus an edge 1 - 3.
+        let adjacency = TestMatrix([
+            0, 1, 0, 0,
+            1, 0, 1, 1,
+            0, 1, 0, 0,
+            0, 1, 0, 0,
+        ]);
+        let l = adjacency.laplacian();
+
+        assert_eq!(l, TestMatrix([
+            1, -1, 0, 0,
+            -1, 3, -1, -1,
+            0, -1, 1, 0,
+            0, -1, 0, 1,
+        ]));
+        for row in l.0.chunks(4) {
+            assert_eq!(row.iter().sum::<i32>(), 0);
+        }
+    }
+
+    #[test]
+    fn transpose_all() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let mut matrices = [
+            TestMatrix([1, 2, 3, 4]),
+            TestMatrix([5, 6, 7, 8]),
+            TestMatrix::identity(),
+        ];
+        TestMatrix::transpose_all(&mut matrices);
+
+        assert_eq!(matrices[0], TestMatrix([1, 3, 2, 4]));
+        assert_eq!(matrices[1], TestMatrix([5, 7, 6, 8]));
+        assert!(matrices[2].is_identity());
+    }
+
+    #[test]
+    fn numerical_rank() {
+        impl_matrix!(TestMatrix([f64; (3, 3)]));
+        impl_matrix!(WideMatrix([f64; (2, 4)]));
+        // The third row is the sum of the first two.
         let m = TestMatrix([
-                           0, 1, 2, 3, 4, 5,
-                           6, 7, 8, 9, 10, 11,
-                           12, 13, 14, 15, 16, 17,
-                           18, 19, 20, 21, 22, 23,
-                           24, 25, 26, 27, 28, 29,
-                           30, 31, 32, 33, 34, 35
+            1., 2., 3.,
+            4., 5., 6.,
+            5., 7., 9.,
         ]);
-        let trans = m.transpose();
+        assert_eq!(m.numerical_rank(1e-9), 2);
 
-        assert_eq!(trans,
-                   TestMatrix([
-                              0, 6, 12, 18, 24, 30,
-                              1, 7, 13, 19, 25, 31,
-                              2, 8, 14, 20, 26, 32,
-                              3, 9, 15, 21, 27, 33,
-                              4, 10, 16, 22, 28, 34,
-                              5, 11, 17, 23, 29, 35
-                   ]));
+        // Noise below the tolerance does not raise the rank.
+        let mut noisy = TestMatrix([1., 2., 3., 4., 5., 6., 5., 7., 9.]);
+        noisy[(2, 2)] += 1e-8;
+        assert_eq!(noisy.numerical_rank(1e-6), 2);
+        assert_eq!(noisy.numerical_rank(1e-12), 3);
+
+        assert_eq!(TestMatrix::identity().numerical_rank(1e-9), 3);
+        assert_eq!(TestMatrix([0.; 9]).numerical_rank(1e-9), 0);
+        assert_eq!(WideMatrix([1., 0., 2., 0., 0., 1., 0., 3.]).numerical_rank(1e-9), 2);
     }
 
     #[test]
-    fn index() {
-        impl_matrix!(TestMatrix([i32; (3, 3)]));
-        let m = TestMatrix([1, 2, 3, 4, 5, 6, 7, 8, 9]);
-        assert_eq!(m[(1, 2)], 6);
+    fn eigen_symmetric() {
+        impl_matrix!(TestMatrix([f64; (3, 3)]));
+        let m = TestMatrix([
+            4., 1., 2.,
+            1., 3., 0.,
+            2., 0., 5.,
+        ]);
+        let (values, v) = m.eigen_symmetric();
+
+        assert!(values[0] >= values[1] && values[1] >= values[2]);
+        assert!((values.iter().sum::<f64>() - 12.).abs() < 1e-9);
+        // V * diag(values) * V^T reconstructs the matrix.
+        for i in 0..3 {
+            for j in 0..3 {
+                let mut x = 0.;
+                for (k, value) in values.iter().enumerate() {
+                    x += v[(i, k)] * value * v[(j, k)];
+                }
+                assert!((x - m[(i, j)]).abs() < 1e-9);
+            }
+        }
+
+        // NaN input gives NaN eigenvalues instead of a panic while sorting them.
+        let (values, _) = TestMatrix([f64::NAN, 0., 0., 0., 1., 0., 0., 0., 1.]).eigen_symmetric();
+        assert!(values.iter().any(|x| x.is_nan()));
     }
 
     #[test]
-    fn index_mut() {
-        impl_matrix!(TestMatrix([i32; (3, 3)]));
-        let mut m = TestMatrix([1, 2, 3, 4, 5, 6, 7, 8, 9]);
-        assert_eq!(m[(1, 2)], 6);
-        m[(1, 2)] = 30;
-        assert_eq!(m[(1, 2)], 30);
+    fn pca() {
+        impl_matrix!(TestMatrix([f64; (5, 2)]));
+        // Samples along y = 2x, nudged off the line in alternating directions.
+        let data = TestMatrix([
+            0., 0.1,
+            1., 1.9,
+            2., 4.1,
+            3., 5.9,
+            4., 8.,
+        ]);
+        let (values, components) = data.pca(1);
+
+        assert_eq!(values.len(), 1);
+        assert_eq!(components.len(), 1);
+        let (dx, dy) = (components[0][0], components[0][1]);
+        assert!(((dx * dx + dy * dy).sqrt() - 1.).abs() < 1e-9);
+        assert!((dy / dx - 2.).abs() < 0.05);
+
+        let (all, _) = data.pca(2);
+        assert!(all[0] > 100. * all[1]);
     }
 
     #[test]
-    fn eq() {
-        impl_matrix!(TestMatrix([i32; (3, 3)]));
-        let m_a = TestMatrix([1, 2, 3, 4, 5, 6, 7, 8, 9]);
-        let m_b = TestMatrix([1, 2, 3, 4, 5, 6, 7, 8, 9]);
+    // The 1x1 case makes some of the generated loops trivially empty.
+    #[allow(clippy::modulo_one, clippy::reversed_empty_ranges)]
+    fn determinant() {
+        impl_matrix!(Matrix1([f64; (1, 1)]));
+        impl_matrix!(Matrix2([f64; (2, 2)]));
+        impl_matrix!(Matrix3([f64; (3, 3)]));
+        impl_matrix!(Matrix4([f64; (4, 4)]));
+        assert_eq!(Matrix1([-2.5]).determinant(), -2.5);
+        assert_eq!(Matrix2([3., 8., 4., 6.]).determinant(), 3. * 6. - 8. * 4.);
+        assert_eq!(Matrix3([6., 1., 1., 4., -2., 5., 2., 8., 7.]).determinant(), -306.);
 
-        assert_eq!(m_a, m_b);
+        // A zero in the top left needs a row swap.
+        let m = Matrix4([
+            0., 2., 1., 3.,
+            1., 0., 2., 1.,
+            2., 1., 0., 1.,
+            1., 1., 1., 0.,
+        ]);
+        assert!((m.determinant() - m.determinant_f64()).abs() < 1e-9);
+        assert!((m.determinant() + 15.).abs() < 1e-9);
+        assert_eq!(Matrix4([1.; 16]).determinant(), 0.);
+
+        impl_matrix!(IntMatrix([i64; (5, 5)]));
+        let exact = IntMatrix([
+            -100, 593, 315, -15, 4,
+            200, 214, -2, 4, 4,
+            100, 7, -3, 5, 1,
+            0, 200, -596, 378, 7,
+            100, 7, 297, 813, 506,
+        ]);
+        assert_eq!(exact.determinant(), -1_200_000_000_000);
     }
 
     #[test]
-    fn add() {
-        impl_matrix!(TestMatrix([i32; (2, 2)]));
-        let m_a = TestMatrix([1, 2, 3, 4]);
-        let m_b = TestMatrix([1, 2, 3, 4]);
-        let m_c = m_a + m_b;
+    fn determinant_4x4() {
+        impl_matrix!(TestMatrix([f64; (4, 4)]));
+        let matrices = [
+            TestMatrix::identity(),
+            TestMatrix([
+                0., 2., 1., 3.,
+                1., 0., 2., 1.,
+                2., 1., 0., 1.,
+                1., 1., 1., 0.,
+            ]),
+            TestMatrix([
+                3., -1., 4., 1.,
+                5., 9., -2., 6.,
+                5., 3., 5., -8.,
+                9., 7., 9., 3.,
+            ]),
+            // A translation with a rotation and scale, as in a graphics transform.
+            TestMatrix([
+                0., -2., 0., 5.,
+                2., 0., 0., -1.,
+                0., 0., 2., 3.,
+                0., 0., 0., 1.,
+            ]),
+            TestMatrix([1.; 16]),
+        ];
+        for m in matrices.iter() {
+            assert!((m.determinant_4x4() - m.determinant()).abs() < 1e-9);
+        }
+        assert_eq!(matrices[1].determinant_4x4(), -15.);
 
-        assert_eq!(m_c, TestMatrix([2, 4, 6, 8]));
+        impl_matrix!(IntMatrix([i32; (4, 4)]));
+        let exact = IntMatrix([2, 0, 1, 3, 1, 4, 0, -2, 0, 1, 5, 1, 3, 2, 1, 0]);
+        assert_eq!(exact.determinant_4x4(), exact.determinant());
     }
 
     #[test]
-    fn add_assign() {
-        impl_matrix!(TestMatrix([i32; (2, 2)]));
-        let mut m_a = TestMatrix([1, 2, 3, 4]);
-        let m_b = TestMatrix([1, 2, 3, 4]);
-        m_a += m_b;
+    #[should_panic(expected = "determinant requires a square matrix")]
+    fn determinant_non_square() {
+        impl_matrix!(TestMatrix([f64; (2, 3)]));
+        let _ = TestMatrix([1.; 6]).determinant();
+    }
 
-        assert_eq!(m_a, TestMatrix([2, 4, 6, 8]));
+    #[test]
+    fn powf_matrix() {
+        impl_matrix!(TestMatrix([f64; (2, 2)]));
+        let spd = TestMatrix([5., 2., 2., 2.]);
+        let root = spd.powf_matrix(0.5).unwrap();
+        let square = TestMatrix(root.0) * TestMatrix(root.0);
+        for (actual, expected) in square.0.iter().zip(spd.0.iter()) {
+            assert!((actual - expected).abs() < 1e-9);
+        }
+
+        let inverse_root = spd.powf_matrix(-0.5).unwrap();
+        let whitened = TestMatrix(inverse_root.0) * spd * inverse_root;
+        for (actual, expected) in whitened.0.iter().zip([1., 0., 0., 1.].iter()) {
+            assert!((actual - expected).abs() < 1e-9);
+        }
+
+        // Eigenvalues 3 and -1.
+        let indefinite = TestMatrix([1., 2., 2., 1.]);
+        assert!(indefinite.powf_matrix(0.5).is_none());
+        assert!(indefinite.powf_matrix(2.).is_some());
     }
 
     #[test]
-    fn add_scalar() {
-        impl_matrix!(TestMatrix([i32; (2, 2)]));
-        let m_a = TestMatrix([1, 2, 3, 4]);
-        let m_b = m_a + 1;
+    fn powf_matrix_singular() {
+        impl_matrix!(TestMatrix([f64; (3, 3)]));
+        // v * v^T for v = (1, 2, 3). Rounding turns one of its zero eigenvalues slightly negative.
+        let m = TestMatrix([
+            1., 2., 3.,
+            2., 4., 6.,
+            3., 6., 9.,
+        ]);
+        let root = m.powf_matrix(0.5).unwrap();
+        let square = root * root;
+        for (actual, expected) in square.0.iter().zip(m.0.iter()) {
+            assert!((actual - expected).abs() < 1e-9);
+        }
+        assert!(m.powf_matrix(-0.5).is_none());
+    }
 
-        assert_eq!(m_b, TestMatrix([2, 3, 4, 5]));
+    #[test]
+    fn inverse() {
+        impl_matrix!(TestMatrix([f64; (3, 3)]));
+        let a = TestMatrix([
+            2., -1., 0.,
+            -1., 2., -1.,
+            0., -1., 2.,
+        ]);
+        let product = TestMatrix(a.0) * a.inverse().unwrap();
+        for (actual, expected) in product.0.iter().zip(TestMatrix::identity().0.iter()) {
+            assert!((actual - expected).abs() < 1e-12);
+        }
+
+        // The zero in the top left can only be used after a row swap.
+        let swapped = TestMatrix([
+            0., 1., 0.,
+            3., 0., 0.,
+            0., 0., 0.5,
+        ]);
+        assert_eq!(swapped.inverse().unwrap(), TestMatrix([
+            0., 1. / 3., 0.,
+            1., 0., 0.,
+            0., 0., 2.,
+        ]));
+        // The exchange matrix has an exact zero as its leading pivot, so it needs a row swap,
+        // and it is its own inverse.
+        impl_matrix!(Exchange([f64; (2, 2)]));
+        assert_eq!(Exchange([0., 1., 1., 0.]).inverse(), Some(Exchange([0., 1., 1., 0.])));
+
+        assert!(TestMatrix([1., 2., 3., 4., 5., 6., 7., 8., 9.]).inverse().is_none());
+        assert!(TestMatrix([0.; 9]).inverse().is_none());
     }
 
     #[test]
-    fn add_scalar_assign() {
-        impl_matrix!(TestMatrix([i32; (2, 2)]));
-        let mut m_a = TestMatrix([1, 2, 3, 4]);
-        m_a += 1;
+    fn bytes_round_trip() {
+        use ParseError;
+        impl_matrix!(TestMatrix([f64; (2, 3)]));
+        impl_matrix!(SquareMatrix([f64; (3, 3)]));
+        impl_matrix!(IntMatrix([i64; (2, 3)]));
+        let m = TestMatrix([1.5, -0., f64::MAX, f64::MIN_POSITIVE, 1e-300, -7.25]);
+        let bytes = m.to_bytes();
 
-        assert_eq!(m_a, TestMatrix([2, 3, 4, 5]));
+        assert_eq!(bytes.len(), 9 + 6 * 8);
+        assert_eq!(TestMatrix::from_bytes(&bytes), Ok(m));
+        assert_eq!(SquareMatrix::from_bytes(&bytes).err(),
+                   Some(ParseError::RowCount { expected: 3, found: 2 }));
+        assert_eq!(IntMatrix::from_bytes(&bytes).err(),
+                   Some(ParseError::ElementType { expected: 4, found: 12 }));
+        assert_eq!(TestMatrix::from_bytes(&bytes[..20]).err(),
+                   Some(ParseError::ByteLength { expected: 57, found: 20 }));
     }
 
     #[test]
-    fn subtract() {
-        impl_matrix!(TestMatrix([i32; (2, 2)]));
-        let m_a = TestMatrix([1, 2, 3, 4]);
-        let m_b = TestMatrix([1, 2, 3, 4]);
-        let m_c = m_a - m_b;
+    fn find_non_finite() {
+        impl_matrix!(TestMatrix([f64; (2, 3)]));
+        let mut m = TestMatrix([1., 2., 3., 4., 5., 6.]);
+        assert!(!m.has_non_finite());
+        assert!(m.find_non_finite().is_empty());
 
-        assert_eq!(m_c, TestMatrix([0, 0, 0, 0]));
+        m[(1, 0)] = f64::NAN;
+        assert!(m.has_non_finite());
+        assert_eq!(m.find_non_finite(), vec![(1, 0)]);
+
+        m[(0, 2)] = f64::NEG_INFINITY;
+        assert_eq!(m.find_non_finite(), vec![(0, 2), (1, 0)]);
     }
 
     #[test]
-    fn subtract_assign() {
-        impl_matrix!(TestMatrix([i32; (2, 2)]));
-        let mut m_a = TestMatrix([1, 2, 3, 4]);
-        let m_b = TestMatrix([1, 2, 3, 4]);
-        m_a -= m_b;
+    fn visibility() {
+        mod shapes {
+            use ArrayMatrix;
+            use std::ops::{Index, IndexMut, Add, AddAssign, Sub, SubAssign, Mul, MulAssign, Div, DivAssign};
+            use std::fmt;
 
-        assert_eq!(m_a, TestMatrix([0, 0, 0, 0]));
+            impl_matrix!(pub PublicMatrix([i32; (2, 2)]));
+            impl_matrix!(pub(crate) CrateMatrix([i32; (2, 2)]));
+        }
+
+        let m = shapes::PublicMatrix::from_array([1, 2, 3, 4]);
+        assert_eq!(m.transpose(), shapes::PublicMatrix::from_array([1, 3, 2, 4]));
+        assert!(shapes::CrateMatrix::identity().is_identity());
     }
 
     #[test]
-    fn subtract_scalar() {
-        impl_matrix!(TestMatrix([i32; (2, 2)]));
-        let m_a = TestMatrix([1, 2, 3, 4]);
-        let m_b = m_a - 1;
+    fn axpy() {
+        impl_matrix!(TestMatrix([f64; (2, 2)]));
+        let a = TestMatrix([1., 2., 3., 4.]);
+        let b = TestMatrix([0.5, -1., 2., 0.]);
+        let mut m = a;
+        m.axpy(2.0, &b);
 
-        assert_eq!(m_b, TestMatrix([0, 1, 2, 3]));
+        assert_eq!(m, a + b * 2.0);
     }
 
     #[test]
-    fn subtract_scalar_assign() {
+    fn scale_add_assign() {
+        impl_matrix!(TestMatrix([f64; (2, 3)]));
+        let mut fused = TestMatrix([1., -2., 3.5, 0., 10., -0.25]);
+        let mut two_step = fused;
+        fused.scale_add_assign(0.5, -1.);
+        two_step *= 0.5;
+        two_step += -1.;
+
+        assert_eq!(fused, two_step);
+        assert_eq!(fused, TestMatrix([-0.5, -2., 0.75, -1., 4., -1.125]));
+    }
+
+    #[test]
+    fn to_homogeneous() {
         impl_matrix!(TestMatrix([i32; (2, 2)]));
-        let mut m_a = TestMatrix([1, 2, 3, 4]);
-        m_a -= 1;
+        impl_matrix!(HomogeneousMatrix([i32; (3, 3)]));
+        let rotation = TestMatrix([0, -1, 1, 0]);
+        let h = HomogeneousMatrix::from_rows_vec(rotation.to_homogeneous()).unwrap();
 
-        assert_eq!(m_a, TestMatrix([0, 1, 2, 3]));
+        assert_eq!(h, HomogeneousMatrix([
+            0, -1, 0,
+            1, 0, 0,
+            0, 0, 1,
+        ]));
     }
 
-//    #[test]
-//    fn multiply() {
-//        impl_matrix!(TestMatrix([i32; (2, 2)]));
-//        let m_a = TestMatrix([1, 2, 3, 4]);
-//        let m_b = TestMatrix([1, 2, 3, 4]);
-//        let m_c = m_a * m_b;
-//
-//        assert_eq!(m_c[..], [7, 22]);
-//    }
+    #[test]
+    fn rank_nullity() {
+        impl_matrix!(TestMatrix([i32; (3, 4)]));
+        // The third row is the first plus twice the second.
+        let m = TestMatrix([
+            1, 2, 0, 1,
+            0, 1, 1, 0,
+            1, 4, 2, 1,
+        ]);
+        assert_eq!(m.rank(), 2);
+        assert_eq!(m.nullity(), 2);
+        assert_eq!(m.rank() + m.nullity(), m.column());
+
+        impl_matrix!(FloatMatrix([f64; (3, 3)]));
+        let m = FloatMatrix([0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9]);
+        assert_eq!(m.rank(), 2);
+        assert_eq!(FloatMatrix::identity().nullity(), 0);
+        assert_eq!(FloatMatrix([0.; 9]).nullity(), 3);
+    }
 
     #[test]
-    fn multiply_scalar() {
-        impl_matrix!(TestMatrix([i32; (2, 2)]));
-        let m_a = TestMatrix([1, 2, 3, 4]);
-        let m_b = m_a * 3;
+    fn null_space() {
+        impl_matrix!(TestMatrix([f64; (3, 3)]));
+        // Every row is a multiple of (1, 2, 3).
+        let m = TestMatrix([
+            1., 2., 3.,
+            2., 4., 6.,
+            -1., -2., -3.,
+        ]);
+        let basis = m.null_space();
 
-        assert_eq!(m_b, TestMatrix([3, 6, 9, 12]));
+        assert_eq!(basis, vec![vec![-2., 1., 0.], vec![-3., 0., 1.]]);
+        for v in basis.iter() {
+            for i in 0..3 {
+                let dot: f64 = (0..3).map(|j| m[(i, j)] * v[j]).sum();
+                assert!(dot.abs() < 1e-12);
+            }
+        }
+        assert!(TestMatrix::identity().null_space().is_empty());
     }
 
     #[test]
-    fn multiply_scalar_assign() {
-        impl_matrix!(TestMatrix([i32; (2, 2)]));
-        let mut m_a = TestMatrix([1, 2, 3, 4]);
-        m_a *= 3;
+    fn pseudo_inverse() {
+        impl_matrix!(TallMatrix([f64; (3, 2)]), transpose: WideMatrix);
+        impl_matrix!(WideMatrix([f64; (2, 3)]), transpose: TallMatrix);
+        let a = TallMatrix([
+            1., 0.,
+            1., 1.,
+            1., 2.,
+        ]);
+        let pinv = a.pseudo_inverse().unwrap();
+        for i in 0..2 {
+            for j in 0..2 {
+                let x: f64 = (0..3).map(|k| pinv[(i, k)] * a[(k, j)]).sum();
+                let expected = if i == j { 1. } else { 0. };
+                assert!((x - expected).abs() < 1e-12);
+            }
+        }
 
-        assert_eq!(m_a, TestMatrix([3, 6, 9, 12]));
+        // Parallel columns do not have full column rank.
+        assert!(TallMatrix([1., 2., 2., 4., 3., 6.]).pseudo_inverse().is_none());
+
+        // The rank test does not depend on the scale of A.
+        let tiny = TallMatrix(a.0.map(|x| x * 1e-9)).pseudo_inverse().unwrap();
+        for (x, y) in tiny.0.iter().zip(pinv.0.iter()) {
+            assert!((x * 1e-9 - y).abs() < 1e-12);
+        }
+        assert!(TallMatrix([1e-9, 2e-9, 2e-9, 4e-9, 3e-9, 6e-9]).pseudo_inverse().is_none());
     }
 
     #[test]
-    fn divide_scalar() {
-        impl_matrix!(TestMatrix([i32; (2, 2)]));
-        let m_a = TestMatrix([9, 12, 21, 36]);
-        let m_b = m_a / 3;
+    fn nearest_axis_aligned() {
+        impl_matrix!(TestMatrix([f64; (3, 3)]));
+        // A 90 degree turn about z, knocked slightly off by accumulated error.
+        let drifted = TestMatrix([
+            0.02, -0.999, 0.01,
+            0.998, 0.03, -0.02,
+            -0.01, 0.015, 1.001,
+        ]);
 
-        assert_eq!(m_b, TestMatrix([3, 4, 7, 12]));
+        assert_eq!(drifted.nearest_axis_aligned(), TestMatrix([
+            0., -1., 0.,
+            1., 0., 0.,
+            0., 0., 1.,
+        ]));
+        assert!(TestMatrix::identity().nearest_axis_aligned().is_identity());
     }
 
     #[test]
-    fn divide_scalar_assign() {
+    fn quaternion_round_trip() {
+        impl_matrix!(TestMatrix([f64; (3, 3)]));
+        let (s, c) = (0.6f64, 0.8f64);
+        let rotations = [
+            TestMatrix::identity(),
+            // About z.
+            TestMatrix([c, -s, 0., s, c, 0., 0., 0., 1.]),
+            // 180 degrees about x, where the trace is negative.
+            TestMatrix([1., 0., 0., 0., -1., 0., 0., 0., -1.]),
+            // 180 degrees about y and about z.
+            TestMatrix([-1., 0., 0., 0., 1., 0., 0., 0., -1.]),
+            TestMatrix([-1., 0., 0., 0., -1., 0., 0., 0., 1.]),
+            // About z, then about x.
+            TestMatrix([c, -s, 0., s, c, 0., 0., 0., 1.])
+                * TestMatrix([1., 0., 0., 0., c, -s, 0., s, c]),
+        ];
+        for r in rotations.iter() {
+            let q = r.to_quaternion();
+            let norm: f64 = q.iter().map(|x| x * x).sum();
+            assert!((norm - 1.).abs() < 1e-12);
+            let back = TestMatrix::from_quaternion(q);
+            for k in 0..9 {
+                assert!((back.0[k] - r.0[k]).abs() < 1e-12, "{:?} -> {:?} -> {:?}", r, q, back);
+            }
+        }
+
+        assert_eq!(TestMatrix::identity().to_quaternion(), [1., 0., 0., 0.]);
+    }
+
+    #[test]
+    fn row_and_col_iter() {
+        impl_matrix!(TestMatrix([i32; (3, 3)]));
+        let m = TestMatrix([1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(m.row_iter(1).cloned().collect::<Vec<_>>(), vec![4, 5, 6]);
+        assert_eq!(m.col_iter(2).cloned().collect::<Vec<_>>(), vec![3, 6, 9]);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds for 3 columns")]
+    fn col_iter_out_of_bounds() {
+        impl_matrix!(TestMatrix([i32; (3, 3)]));
+        let _ = TestMatrix([1, 2, 3, 4, 5, 6, 7, 8, 9]).col_iter(3);
+    }
+
+    #[test]
+    fn inverse_and_det() {
+        impl_matrix!(TestMatrix([f64; (3, 3)]));
+        let matrices = [
+            TestMatrix([2., -1., 0., -1., 2., -1., 0., -1., 2.]),
+            // Needs a row swap, which flips the sign.
+            TestMatrix([0., 1., 0., 3., 0., 0., 0., 0., 2.]),
+            TestMatrix([6., 1., 1., 4., -2., 5., 2., 8., 7.]),
+        ];
+        for a in matrices.iter() {
+            let (inv, det) = a.inverse_and_det().unwrap();
+            assert!((det - a.determinant()).abs() < 1e-9, "{} != {}", det, a.determinant());
+            let product = *a * inv;
+            for (actual, expected) in product.0.iter().zip(TestMatrix::identity().0.iter()) {
+                assert!((actual - expected).abs() < 1e-12);
+            }
+        }
+
+        let singular = TestMatrix([1., 2., 3., 2., 4., 6., 0., 1., 1.]);
+        assert!(singular.inverse_and_det().is_none());
+    }
+
+    #[test]
+    fn inverse_ill_conditioned() {
+        impl_matrix!(TestMatrix([f32; (2, 2)]));
+        impl_matrix!(IntMatrix([i32; (2, 2)]));
+        // Nearly singular, so rounding the inverse to f32 leaves `m * inv` far from the identity,
+        // but no further than the condition of `m` allows. The debug check accepts it.
+        let m = TestMatrix([0.1, 0.7, 0.3, 2.1000001]);
+        assert!(m.inverse().is_some());
+        // Integer elements truncate the inverse and are not checked.
+        assert_eq!(IntMatrix([2, 0, 0, 2]).inverse(), Some(IntMatrix([0; 4])));
+    }
+
+    #[test]
+    #[should_panic(expected = "inverse lost accuracy")]
+    fn inverse_check_rejects_bad_inverse() {
+        use linalg;
+        // The identity is no inverse of 2 * I.
+        let (a, bad) = ([2., 0., 0., 2.], [1., 0., 0., 1.]);
+        linalg::check_solution("inverse", &a, &bad, &[1., 0., 0., 1.], 2, f64::EPSILON);
+    }
+
+    #[test]
+    fn default() {
+        impl_matrix!(TestMatrix([i32; (2, 3)]));
+        assert_eq!(TestMatrix::default(), TestMatrix([0; 6]));
+
+        #[derive(Default)]
+        struct Transform {
+            matrix: TestMatrix,
+        }
+        assert_eq!(Transform::default().matrix, TestMatrix::zeros());
+    }
+
+    #[test]
+    fn is_rotation() {
+        impl_matrix!(TestMatrix([f64; (3, 3)]));
+        let (s, c) = (0.6, 0.8);
+        let rotation = TestMatrix([c, -s, 0., s, c, 0., 0., 0., 1.]);
+        assert!(rotation.is_orthogonal(1e-12));
+        assert!(rotation.is_rotation(1e-12));
+
+        // Mirroring the z axis keeps the matrix orthogonal but flips the determinant.
+        let reflection = TestMatrix([c, -s, 0., s, c, 0., 0., 0., -1.]);
+        assert!(reflection.is_orthogonal(1e-12));
+        assert!(!reflection.is_rotation(1e-12));
+
+        let scaled = rotation * 2.;
+        assert!(!scaled.is_orthogonal(1e-12));
+        assert!(!scaled.is_rotation(1e-12));
+
+        let drifted = TestMatrix([c, -s, 1e-9, s, c, 0., 0., 0., 1.]);
+        assert!(drifted.is_rotation(1e-6));
+        assert!(!drifted.is_rotation(1e-12));
+    }
+
+    #[test]
+    fn hadamard() {
         impl_matrix!(TestMatrix([i32; (2, 2)]));
-        let mut m_a = TestMatrix([9, 12, 21, 36]);
-        m_a /= 3;
+        let a = TestMatrix([1, 2, 3, 4]);
+        let b = TestMatrix([5, 6, 7, 8]);
+        assert_eq!(a.hadamard(&b), TestMatrix([5, 12, 21, 32]));
+        assert_eq!(a.hadamard(&TestMatrix::identity()), TestMatrix([1, 0, 0, 4]));
+    }
 
-        assert_eq!(m_a, TestMatrix([3, 4, 7, 12]));
+    #[test]
+    fn orthonormalize_columns() {
+        impl_matrix!(TestMatrix([f64; (3, 3)]));
+        impl_matrix!(Gram([f64; (3, 3)]));
+        let a = TestMatrix([
+            1., 1., 0.,
+            1., 0., 1.,
+            0., 1., 1.,
+        ]);
+        let q = a.orthonormalize_columns();
+        let gram = Gram::from_fn(|(i, j)| (0..3).map(|k| q[(k, i)] * q[(k, j)]).sum());
+        for (actual, expected) in gram.0.iter().zip(Gram::identity().0.iter()) {
+            assert!((actual - expected).abs() < 1e-12);
+        }
+        // The first column keeps its direction.
+        let s = 0.5f64.sqrt();
+        assert!((q[(0, 0)] - s).abs() < 1e-12 && (q[(1, 0)] - s).abs() < 1e-12);
+
+        // The third column is the sum of the first two, so it is zeroed.
+        let dependent = TestMatrix([
+            1., 0., 1.,
+            0., 1., 1.,
+            0., 0., 0.,
+        ]).orthonormalize_columns();
+        assert_eq!(dependent, TestMatrix([
+            1., 0., 0.,
+            0., 1., 0.,
+            0., 0., 0.,
+        ]));
     }
 
     #[test]
-    fn impl_two_matrix() {
-        impl_matrix!(TestMatrixA([i32; (2, 2)]));
-        impl_matrix!(TestMatrixB([f32; (2, 2)]));
+    fn angle_with() {
+        use std::f64::consts::{FRAC_PI_2, PI};
+        impl_matrix!(TestMatrix([f64; (2, 2)]));
+        let a = TestMatrix([1., 2., -3., 0.5]);
+        assert!(a.angle_with(&a).abs() < 1e-7);
+        assert!((a.angle_with(&-a) - PI).abs() < 1e-7);
+        assert!((a.angle_with(&TestMatrix([2., -1., 0., 0.])) - FRAC_PI_2).abs() < 1e-12);
+        assert!((TestMatrix::identity().angle_with(&TestMatrix([1., 0., 0., 0.])) - PI / 4.).abs() < 1e-12);
+        assert_eq!(a.angle_with(&TestMatrix::zeros()), FRAC_PI_2);
+    }
 
-        let m_a = TestMatrixA([1, 2, 3, 4]);
-        let m_b = TestMatrixB([1., 2., 3., 4.]);
+    #[test]
+    fn sum_elements() {
+        use std::iter::Sum;
 
-        assert_eq!(m_a[(0, 0)] as f32, m_b[(0, 0)]);
+        impl_matrix!(TestMatrix([i32; (3, 3)]));
+        assert_eq!(TestMatrix([1, 2, 3, 4, 5, 6, 7, 8, 9]).sum(), 45);
+        assert_eq!(TestMatrix::zeros().sum(), 0);
+
+        // The iterator `Sum` impl still adds matrices together.
+        let total: TestMatrix = Sum::sum(vec![TestMatrix::identity(), TestMatrix::identity()].into_iter());
+        assert_eq!(total.sum(), 6);
+        let total: TestMatrix = vec![TestMatrix::identity(); 3].into_iter().sum();
+        assert_eq!(total.sum(), 9);
     }
 
     #[test]
-    fn large_matrix() {
-        impl_matrix!(TestMatrixA([i32; (6, 6)]));
-        let m_a = TestMatrixA([1; 36]);
-        let m_b = TestMatrixA([1; 36]);
+    fn mul_batch() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let lhs = [TestMatrix([1, 2, 3, 4]), TestMatrix([0, 1, 1, 0]), TestMatrix::identity()];
+        let rhs = [TestMatrix([5, 6, 7, 8]), TestMatrix([1, 2, 3, 4]), TestMatrix([2, 0, 0, 2])];
+        assert_eq!(TestMatrix::mul_batch(&lhs, &rhs), vec![
+            TestMatrix([19, 22, 43, 50]),
+            TestMatrix([3, 4, 1, 2]),
+            TestMatrix([2, 0, 0, 2]),
+        ]);
+        assert!(TestMatrix::mul_batch(&[], &[]).is_empty());
+    }
 
-        assert_eq!(m_a, m_b);
+    #[test]
+    #[should_panic(expected = "batches of equal length")]
+    fn mul_batch_length_mismatch() {
+        impl_matrix!(TestMatrix([i32; (2, 2)]));
+        let _ = TestMatrix::mul_batch(&[TestMatrix::identity()], &[]);
+    }
+
+    #[test]
+    fn min_max() {
+        impl_matrix!(TestMatrix([i32; (2, 3)]));
+        let m = TestMatrix([3, -7, 0, 12, -2, 5]);
+        assert_eq!(m.min(), Some(&-7));
+        assert_eq!(m.max(), Some(&12));
+        assert_eq!(TestMatrix::filled(4).min(), Some(&4));
+
+        impl_matrix!(FloatMatrix([f64; (2, 2)]));
+        let f = FloatMatrix([1.5, -0.5, 2.5, 0.]);
+        assert_eq!(f.min(), Some(&-0.5));
+        assert_eq!(f.max(), Some(&2.5));
+    }
+
+    #[test]
+    fn mean_row() {
+        impl_matrix!(TestMatrix([i32; (3, 2)]));
+        let points = TestMatrix([
+            1, 2,
+            4, -1,
+            -2, 5,
+        ]);
+        assert_eq!(points.mean_row(), vec![1., 2.]);
+        assert_eq!(TestMatrix([1, 0, 0, 0, 0, 0]).mean_row(), vec![1. / 3., 0.]);
     }
 }
@@ -0,0 +1,16 @@
+/// Pivot selection used by Gaussian elimination.
+///
+/// Pivoting trades extra searching for numerical stability. Without it, a tiny pivot
+/// multiplies rounding errors through the rest of the elimination. Partial pivoting bounds the
+/// multipliers by one and is stable for almost all matrices in practice. Full pivoting also
+/// guards against growth in the remaining elements, at the cost of searching the whole
+/// remaining submatrix for every pivot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pivoting {
+    /// Use the diagonal element as the pivot, failing when it is too small to divide by.
+    None,
+    /// Swap in the row with the largest element in the pivot column.
+    Partial,
+    /// Swap in the row and column of the largest element in the remaining submatrix.
+    Full,
+}
@@ -0,0 +1,12 @@
+/// Rounding applied to the quotient of an integer division.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundMode {
+    /// Round toward zero, which is what integer `/` does.
+    Trunc,
+    /// Round toward negative infinity.
+    Floor,
+    /// Round toward positive infinity.
+    Ceil,
+    /// Round to the nearest integer, with halfway cases rounded away from zero.
+    Nearest,
+}
@@ -0,0 +1,84 @@
+/// A sparse matrix in coordinate-list (COO) form that stores only explicitly inserted elements.
+///
+/// Entries are kept sorted in row-major order, so lookups are a binary search and inserting in
+/// row-major order only appends.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseMatrix<T> {
+    rows: usize,
+    columns: usize,
+    entries: Vec<((usize, usize), T)>,
+}
+
+impl<T> SparseMatrix<T> {
+    /// Creates an empty sparse matrix with the given row and column length.
+    pub fn new(rows: usize, columns: usize) -> SparseMatrix<T> {
+        SparseMatrix {
+            rows,
+            columns,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Get the row length.
+    pub fn row(&self) -> usize {
+        self.rows
+    }
+
+    /// Get the column length.
+    pub fn column(&self) -> usize {
+        self.columns
+    }
+
+    /// Get row and column length as a tuple.
+    pub fn size(&self) -> (usize, usize) {
+        (self.rows, self.columns)
+    }
+
+    /// Get the number of stored elements.
+    pub fn nnz(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Get the stored element at `(i, j)`, or `None` if that position holds an implicit zero.
+    pub fn get(&self, pos: (usize, usize)) -> Option<&T> {
+        self.entries
+            .binary_search_by_key(&pos, |&(p, _)| p)
+            .ok()
+            .map(|index| &self.entries[index].1)
+    }
+
+    /// Stores `value` at `(i, j)`, returning the element it replaced if there was one.
+    pub fn insert(&mut self, (i, j): (usize, usize), value: T) -> Option<T> {
+        assert!(i < self.rows && j < self.columns);
+        match self.entries.binary_search_by_key(&(i, j), |&(p, _)| p) {
+            Ok(index) => Some(::std::mem::replace(&mut self.entries[index].1, value)),
+            Err(index) => {
+                self.entries.insert(index, ((i, j), value));
+                None
+            }
+        }
+    }
+
+    /// Get the stored elements with their positions, in row-major order.
+    pub fn entries(&self) -> &[((usize, usize), T)] {
+        &self.entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sparse::SparseMatrix;
+
+    #[test]
+    fn insert_get() {
+        let mut m = SparseMatrix::new(3, 4);
+        assert_eq!(m.insert((2, 1), 5), None);
+        assert_eq!(m.insert((0, 3), 7), None);
+        assert_eq!(m.insert((2, 1), 6), Some(5));
+
+        assert_eq!(m.nnz(), 2);
+        assert_eq!(m.get((2, 1)), Some(&6));
+        assert_eq!(m.get((1, 1)), None);
+        assert_eq!(m.entries(), &[((0, 3), 7), ((2, 1), 6)]);
+    }
+}